@@ -0,0 +1,71 @@
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use cpu_stats::{CPUStatsContext, CoreStats};
+
+mod common;
+
+use common::Simulated;
+
+///
+/// Allocator counting every allocation made by the process
+///
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+const READS: usize = 1000;
+
+///
+/// Average number of allocations made by each call of `f`
+///
+fn allocations_per_call(mut f: impl FnMut()) -> f64 {
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    for _ in 0..READS {
+        f();
+    }
+    (ALLOCATIONS.load(Ordering::Relaxed) - before) as f64 / READS as f64
+}
+
+///
+/// Allocations per read compared with parsing each core line alone, which allocates the one
+/// name per core that the delta returned to the caller owns.
+///
+fn main() {
+    println!("{:>6} {:>12} {:>12} {:>14}", "cores", "read", "parse only", "read per core");
+    for &cores in &[8, 64, 256] {
+        let mut context = CPUStatsContext::from_source(Simulated::new(cores)).unwrap();
+        let read = allocations_per_call(|| drop(context.read().unwrap()));
+
+        let contents = Simulated::new(cores).0;
+        let parse = allocations_per_call(|| {
+            let parsed: Vec<CoreStats> = contents.lines()
+                .filter(|line| line.starts_with("cpu"))
+                .map(|line| CoreStats::from_str(line).unwrap())
+                .collect();
+            drop(parsed);
+        });
+
+        println!("{:>6} {:>12.1} {:>12.1} {:>14.2}", cores, read, parse, read / (cores + 1) as f64);
+    }
+}
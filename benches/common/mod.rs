@@ -0,0 +1,28 @@
+///
+/// Simulated stats shared by the benchmarks
+///
+use std::io;
+
+use cpu_stats::StatsSource;
+
+///
+/// Source returning the same simulated stats file on every read
+///
+pub struct Simulated(pub String);
+
+impl Simulated {
+    pub fn new(cores: usize) -> Self {
+        let mut contents = format!("cpu  {} 0 0 0 0 0 0 0 0 0\n", cores * 10);
+        for core in 0..cores {
+            contents.push_str(&format!("cpu{} 10 2 5 8000 40 1 3 0 0 0\n", core));
+        }
+        contents.push_str("intr 12345 0 0\nctxt 67890\nbtime 1600000000\nprocesses 4321\nprocs_running 2\nprocs_blocked 0\n");
+        Simulated(contents)
+    }
+}
+
+impl StatsSource for Simulated {
+    fn read_stats(&mut self) -> io::Result<String> {
+        Ok(self.0.clone())
+    }
+}
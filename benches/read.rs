@@ -0,0 +1,24 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use cpu_stats::CPUStatsContext;
+
+mod common;
+
+use common::Simulated;
+
+fn read(c: &mut Criterion) {
+    let mut group = c.benchmark_group("read");
+    for &cores in &[8, 64, 256] {
+        group.bench_with_input(BenchmarkId::new("all", cores), &cores, |b, &cores| {
+            let mut context = CPUStatsContext::from_source(Simulated::new(cores)).unwrap();
+            b.iter(|| context.read().unwrap());
+        });
+        group.bench_with_input(BenchmarkId::new("aggregate_only", cores), &cores, |b, &cores| {
+            let mut context = CPUStatsContext::aggregate_only_from_source(Simulated::new(cores)).unwrap();
+            b.iter(|| context.read().unwrap());
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, read);
+criterion_main!(benches);
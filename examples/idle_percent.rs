@@ -14,7 +14,7 @@ fn main() -> io::Result<()> {
         sleep(delay);
 
         for core in stats.read()?.iter() {
-            if !core.stats.is_aggregate() {
+            if !core.is_aggregate() {
                 print!("{:3}% ", 100 - core.idle_percent())
             }
         }
@@ -0,0 +1,23 @@
+///
+/// CPU affinity of the calling thread
+///
+use std::collections::BTreeSet;
+use std::io;
+use std::mem;
+
+///
+/// Indexes of the cores the calling thread is allowed to run on
+///
+pub(crate) fn thread_affinity() -> io::Result<BTreeSet<usize>> {
+    // Safety: cpu_set_t is a plain bitmask so all zeros is a valid (empty) value and the
+    // kernel writes at most size_of::<cpu_set_t>() bytes into it.
+    let mut set: libc::cpu_set_t = unsafe { mem::zeroed() };
+    let result = unsafe { libc::sched_getaffinity(0, mem::size_of::<libc::cpu_set_t>(), &mut set) };
+    if result != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok((0..libc::CPU_SETSIZE as usize)
+        .filter(|cpu| unsafe { libc::CPU_ISSET(*cpu, &set) })
+        .collect())
+}
@@ -0,0 +1,91 @@
+///
+/// Usage alerts with hysteresis
+///
+use std::collections::BTreeSet;
+
+use crate::CoreSnapshot;
+
+///
+/// Tracks which cores are in an alert state.
+///
+/// A core enters the alert state when its usage rises above the `on` threshold and only
+/// leaves it once usage falls below the `off` threshold, so usage hovering around a single
+/// threshold does not flap between states. The aggregate is ignored.
+///
+pub struct AlertState {
+    on_threshold: f64,
+    off_threshold: f64,
+    alerting: BTreeSet<String>,
+}
+
+impl AlertState {
+    ///
+    /// Create alert state, `off_threshold` should be below `on_threshold`.
+    ///
+    pub fn new(on_threshold: f64, off_threshold: f64) -> Self {
+        Self {
+            on_threshold,
+            off_threshold,
+            alerting: BTreeSet::new(),
+        }
+    }
+
+    ///
+    /// Update state from the snapshots of a read.
+    ///
+    pub fn update(&mut self, snapshots: &[CoreSnapshot]) {
+        for core in snapshots.iter().filter(|core| !core.is_aggregate()) {
+            let usage = core.usage_percent();
+            if usage > self.on_threshold {
+                self.alerting.insert(core.stats.name.clone());
+            } else if usage < self.off_threshold {
+                self.alerting.remove(&core.stats.name);
+            }
+        }
+    }
+
+    ///
+    /// Is the named core currently in the alert state
+    ///
+    pub fn is_alerting(&self, name: &str) -> bool {
+        self.alerting.contains(name)
+    }
+
+    ///
+    /// Names of cores currently in the alert state
+    ///
+    pub fn alerting(&self) -> Vec<&str> {
+        self.alerting.iter().map(String::as_str).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core_snapshot;
+
+    #[test]
+    fn no_flapping_between_thresholds() {
+        let mut state = AlertState::new(90.0, 70.0);
+
+        state.update(&[core_snapshot("cpu0 85 0 0 0 0 0 0 0 0 0", 1000), core_snapshot("cpu1 50 0 0 0 0 0 0 0 0 0", 1000)]);
+        assert!(state.alerting().is_empty());
+
+        state.update(&[core_snapshot("cpu0 95 0 0 0 0 0 0 0 0 0", 1000), core_snapshot("cpu1 50 0 0 0 0 0 0 0 0 0", 1000)]);
+        assert_eq!(state.alerting(), vec!["cpu0"]);
+
+        for usage in &[85, 75, 89, 71, 80] {
+            state.update(&[
+                core_snapshot(&format!("cpu0 {} 0 0 0 0 0 0 0 0 0", usage), 1000),
+                core_snapshot("cpu1 50 0 0 0 0 0 0 0 0 0", 1000),
+            ]);
+            assert!(state.is_alerting("cpu0"));
+        }
+
+        state.update(&[core_snapshot("cpu0 65 0 0 0 0 0 0 0 0 0", 1000), core_snapshot("cpu1 50 0 0 0 0 0 0 0 0 0", 1000)]);
+        assert!(!state.is_alerting("cpu0"));
+
+        state.update(&[core_snapshot("cpu0 85 0 0 0 0 0 0 0 0 0", 1000), core_snapshot("cpu1 50 0 0 0 0 0 0 0 0 0", 1000)]);
+        assert!(!state.is_alerting("cpu0"));
+    }
+}
@@ -0,0 +1,104 @@
+///
+/// Mutually exclusive breakdown of a period by time category
+///
+use crate::CoreSnapshot;
+
+///
+/// Percentage of the time accounted for in a snapshot spent in each category.
+///
+/// The kernel counts guest time within user and guest_nice within nice, here they are
+/// subtracted so no time is counted twice and the categories sum to 100.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Breakdown {
+    pub user: f64,
+    pub nice: f64,
+    pub system: f64,
+    pub idle: f64,
+    pub iowait: f64,
+    pub irq: f64,
+    pub softirq: f64,
+    pub steal: f64,
+    pub guest: f64,
+    pub guest_nice: f64,
+}
+
+impl Breakdown {
+    ///
+    /// Sum of all categories, 100 unless no time was accounted for
+    ///
+    pub fn total(&self) -> f64 {
+        self.user + self.nice + self.system + self.idle + self.iowait
+            + self.irq + self.softirq + self.steal + self.guest + self.guest_nice
+    }
+}
+
+impl CoreSnapshot {
+    ///
+    /// Share of the accounted time spent in each category, all zero if no time was accounted.
+    ///
+    /// Percentages are of the sum of the counters rather than the period so they sum to 100
+    /// regardless of timing jitter.
+    ///
+    pub fn breakdown(&self) -> Breakdown {
+        let stats = &self.stats;
+        let jiffies = [
+            stats.user_processes.saturating_sub(stats.guest),
+            stats.nice_processes.saturating_sub(stats.guest_nice),
+            stats.system_processes,
+            stats.idle_time,
+            stats.io_wait,
+            stats.irq,
+            stats.soft_irq,
+            stats.steal_time,
+            stats.guest,
+            stats.guest_nice,
+        ];
+        let total: f64 = jiffies.iter().map(|&value| value as f64).sum();
+        if total == 0.0 {
+            return Breakdown::default();
+        }
+        let percent = |index: usize| jiffies[index] as f64 * 100.0 / total;
+
+        Breakdown {
+            user: percent(0),
+            nice: percent(1),
+            system: percent(2),
+            idle: percent(3),
+            iowait: percent(4),
+            irq: percent(5),
+            softirq: percent(6),
+            steal: percent(7),
+            guest: percent(8),
+            guest_nice: percent(9),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+    use std::time::Duration;
+    use crate::CoreStats;
+
+    #[test]
+    fn breakdown() {
+        let stats = CoreStats::from_str("cpu0 40 10 10 40 0 0 0 0 20 5").unwrap();
+        let breakdown = CoreSnapshot::new(stats, Duration::from_millis(1000)).breakdown();
+
+        assert_eq!(breakdown, Breakdown {
+            user: 20.0,
+            nice: 5.0,
+            system: 10.0,
+            idle: 40.0,
+            guest: 20.0,
+            guest_nice: 5.0,
+            ..Breakdown::default()
+        });
+        assert_eq!(breakdown.total(), 100.0);
+
+        let idle = CoreStats::from_str("cpu0 0 0 0 0 0 0 0 0 0 0").unwrap();
+        assert_eq!(CoreSnapshot::new(idle, Duration::from_millis(1000)).breakdown().total(), 0.0);
+    }
+}
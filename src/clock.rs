@@ -0,0 +1,23 @@
+///
+/// Time sources used to measure read periods
+///
+use std::time::Instant;
+
+///
+/// Supplies the current instant, replace to control time in tests.
+///
+pub trait Clock: Send {
+    fn now(&self) -> Instant;
+}
+
+///
+/// The real monotonic clock
+///
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
@@ -0,0 +1,71 @@
+///
+/// Comparison of readings for A/B testing
+///
+use crate::CoreSnapshot;
+
+///
+/// Usage of a core in two sets of snapshots, `None` where the core is missing from a set
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct CoreComparison {
+    pub name: String,
+    pub usage_a: Option<f64>,
+    pub usage_b: Option<f64>,
+}
+
+impl CoreComparison {
+    ///
+    /// Usage in b minus usage in a, `None` unless the core is in both sets
+    ///
+    pub fn difference(&self) -> Option<f64> {
+        Some(self.usage_b? - self.usage_a?)
+    }
+}
+
+///
+/// Compare the usage of each core between two sets of snapshots, matched by name.
+///
+/// Cores are in the order of `a` followed by any cores only in `b`.
+///
+pub fn compare(a: &[CoreSnapshot], b: &[CoreSnapshot]) -> Vec<CoreComparison> {
+    let usage_in = |set: &[CoreSnapshot], name: &str| {
+        set.iter().find(|core| core.stats.name == name).map(|core| core.usage_percent())
+    };
+
+    let only_b = b.iter().filter(|core| usage_in(a, &core.stats.name).is_none());
+    a.iter().chain(only_b)
+        .map(|core| CoreComparison {
+            name: core.stats.name.clone(),
+            usage_a: usage_in(a, &core.stats.name),
+            usage_b: usage_in(b, &core.stats.name),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core_snapshot;
+
+    #[test]
+    fn compare() {
+        let a = [
+            core_snapshot("cpu0 40 0 0 60 0 0 0 0 0 0", 1000),
+            core_snapshot("cpu1 20 0 0 80 0 0 0 0 0 0", 1000),
+            core_snapshot("cpu2 10 0 0 90 0 0 0 0 0 0", 1000),
+        ];
+        let b = [
+            core_snapshot("cpu1 50 0 0 50 0 0 0 0 0 0", 1000),
+            core_snapshot("cpu0 30 0 0 70 0 0 0 0 0 0", 1000),
+            core_snapshot("cpu3 70 0 0 30 0 0 0 0 0 0", 1000),
+        ];
+        let comparisons = super::compare(&a, &b);
+
+        let differences: Vec<(&str, Option<f64>)> = comparisons.iter()
+            .map(|comparison| (comparison.name.as_str(), comparison.difference()))
+            .collect();
+        assert_eq!(differences, vec![("cpu0", Some(-10.0)), ("cpu1", Some(30.0)), ("cpu2", None), ("cpu3", None)]);
+        assert_eq!(comparisons[2].usage_a, Some(10.0));
+        assert_eq!(comparisons[3], CoreComparison { name: "cpu3".to_string(), usage_a: None, usage_b: Some(70.0) });
+    }
+}
@@ -0,0 +1,19 @@
+///
+/// Core frequencies from the cpufreq sysfs interface
+///
+use std::fs;
+use std::io;
+use std::path::Path;
+
+///
+/// Current frequency of a core in kHz, `None` if cpufreq is not available for it
+///
+pub(crate) fn current_khz(sysfs_root: &Path, core: &str) -> io::Result<Option<u64>> {
+    let path = sysfs_root.join("devices/system/cpu").join(core).join("cpufreq/scaling_cur_freq");
+    match fs::read_to_string(path) {
+        Ok(contents) => contents.trim().parse().map(Some)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err)),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err),
+    }
+}
@@ -0,0 +1,59 @@
+///
+/// Errors raised while reading CPU statistics
+///
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::io;
+
+#[derive(Debug)]
+pub enum CpuStatsError {
+    ///
+    /// Reading the stats source failed
+    ///
+    Io(io::Error),
+
+    ///
+    /// Line in the stats source could not be parsed
+    ///
+    Parse(String),
+
+    ///
+    /// Stats source did not respond within the configured timeout
+    ///
+    Timeout,
+}
+
+impl Display for CpuStatsError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CpuStatsError::Io(err) => write!(f, "unable to read stats: {}", err),
+            CpuStatsError::Parse(msg) => write!(f, "unable to parse stats: {}", msg),
+            CpuStatsError::Timeout => write!(f, "timed out reading stats"),
+        }
+    }
+}
+
+impl Error for CpuStatsError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            CpuStatsError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for CpuStatsError {
+    fn from(err: io::Error) -> Self {
+        CpuStatsError::Io(err)
+    }
+}
+
+impl From<CpuStatsError> for io::Error {
+    fn from(err: CpuStatsError) -> Self {
+        match err {
+            CpuStatsError::Io(err) => err,
+            CpuStatsError::Parse(_) => io::Error::new(io::ErrorKind::InvalidData, err),
+            CpuStatsError::Timeout => io::Error::new(io::ErrorKind::TimedOut, err),
+        }
+    }
+}
@@ -0,0 +1,256 @@
+///
+/// Rolling window of recent snapshots
+///
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use crate::CoreSnapshot;
+
+///
+/// Aggregate of a single core's usage over the retained window.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct UsageAggregate {
+    pub min: f64,
+    pub max: f64,
+    pub average: f64,
+
+    ///
+    /// Number of samples the core appeared in
+    ///
+    pub samples: usize,
+}
+
+///
+/// Retains the most recent reads up to a fixed capacity, oldest are dropped first.
+///
+pub struct History {
+    capacity: usize,
+    samples: VecDeque<Vec<CoreSnapshot>>,
+}
+
+impl History {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            samples: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    ///
+    /// Add the result of a read, dropping the oldest sample if the window is full.
+    ///
+    pub fn push(&mut self, snapshots: Vec<CoreSnapshot>) {
+        if self.capacity == 0 { return }
+        if self.samples.len() == self.capacity {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(snapshots);
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    ///
+    /// Min, max and average usage of the named core over the window.
+    ///
+    /// Samples the core is absent from are ignored, `None` if it was never seen.
+    ///
+    pub fn stats_for(&self, name: &str) -> Option<UsageAggregate> {
+        let usages = self.samples.iter()
+            .filter_map(|sample| sample.iter().find(|core| core.stats.name == name))
+            .map(|core| core.usage_percent());
+
+        let mut aggregate: Option<UsageAggregate> = None;
+        let mut total = 0.0;
+        for usage in usages {
+            total += usage;
+            let entry = aggregate.get_or_insert(UsageAggregate {
+                min: usage,
+                max: usage,
+                average: 0.0,
+                samples: 0,
+            });
+            entry.min = entry.min.min(usage);
+            entry.max = entry.max.max(usage);
+            entry.samples += 1;
+        }
+
+        aggregate.map(|mut entry| {
+            entry.average = total / entry.samples as f64;
+            entry
+        })
+    }
+
+    ///
+    /// Usage of each core over the window for plotting as a heatmap.
+    ///
+    /// Rows are samples, oldest first, and column `n` is core `cpuN`. There is a column for
+    /// every index up to the highest seen in the window, cores missing from a sample are NaN.
+    /// The aggregate is not included.
+    ///
+    pub fn to_matrix(&self) -> Vec<Vec<f64>> {
+        let columns = self.samples.iter()
+            .flatten()
+            .filter_map(|core| core.stats.index())
+            .max()
+            .map_or(0, |index| index + 1);
+
+        self.samples.iter()
+            .map(|sample| {
+                let mut row = vec![f64::NAN; columns];
+                for core in sample {
+                    if let Some(index) = core.stats.index() {
+                        row[index] = core.usage_percent();
+                    }
+                }
+                row
+            })
+            .collect()
+    }
+}
+
+///
+/// Usage of a single core over a trailing time budget regardless of the sample cadence.
+///
+/// Each delta is retained with the time it was read and dropped once it is older than the
+/// retention period.
+///
+pub struct WindowedUsage {
+    retain: Duration,
+    samples: VecDeque<(Instant, CoreSnapshot)>,
+}
+
+impl WindowedUsage {
+    pub fn new(retain: Duration) -> Self {
+        Self {
+            retain,
+            samples: VecDeque::new(),
+        }
+    }
+
+    ///
+    /// Add a delta read at `at`, dropping samples that have fallen out of the retention period.
+    ///
+    pub fn push(&mut self, at: Instant, snapshot: CoreSnapshot) {
+        self.samples.push_back((at, snapshot));
+        while let Some((oldest, _)) = self.samples.front() {
+            if at.saturating_duration_since(*oldest) <= self.retain { break }
+            self.samples.pop_front();
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    ///
+    /// Usage over samples read within `window` of the most recent one, weighted by period.
+    ///
+    /// `None` if there are no samples or they cover no time.
+    ///
+    pub fn usage_over(&self, window: Duration) -> Option<f64> {
+        let (latest, _) = self.samples.back()?;
+        let (active, period) = self.samples.iter()
+            .filter(|(at, _)| latest.saturating_duration_since(*at) <= window)
+            .fold((0, Duration::ZERO), |(active, period): (u64, Duration), (_, snapshot)| {
+                (active.saturating_add(snapshot.stats.active_time()), period.saturating_add(snapshot.period()))
+            });
+
+        let period_ms = period.as_secs_f64() * 1000.0;
+        if period_ms == 0.0 {
+            return None;
+        }
+        Some(active as f64 * 1000.0 / period_ms)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core_snapshot;
+
+    #[test]
+    fn aggregate_over_window() {
+        let mut history = History::new(3);
+        history.push(vec![core_snapshot("cpu0 90 0 0 0 0 0 0 0 0 0", 1000), core_snapshot("cpu1 10 0 0 0 0 0 0 0 0 0", 1000)]);
+        history.push(vec![core_snapshot("cpu0 10 0 0 0 0 0 0 0 0 0", 1000), core_snapshot("cpu1 20 0 0 0 0 0 0 0 0 0", 1000)]);
+        history.push(vec![core_snapshot("cpu0 20 0 0 0 0 0 0 0 0 0", 1000)]);
+        history.push(vec![core_snapshot("cpu0 30 0 0 0 0 0 0 0 0 0", 1000), core_snapshot("cpu1 60 0 0 0 0 0 0 0 0 0", 1000)]);
+
+        assert_eq!(history.len(), 3);
+        assert_eq!(history.stats_for("cpu0"), Some(UsageAggregate {
+            min: 10.0,
+            max: 30.0,
+            average: 20.0,
+            samples: 3,
+        }));
+        assert_eq!(history.stats_for("cpu1"), Some(UsageAggregate {
+            min: 20.0,
+            max: 60.0,
+            average: 40.0,
+            samples: 2,
+        }));
+        assert_eq!(history.stats_for("cpu7"), None);
+    }
+
+    #[test]
+    fn to_matrix() {
+        let mut history = History::new(3);
+        history.push(vec![
+            core_snapshot("cpu 60 0 0 0 0 0 0 0 0 0", 1000),
+            core_snapshot("cpu0 40 0 0 0 0 0 0 0 0 0", 1000),
+            core_snapshot("cpu1 20 0 0 0 0 0 0 0 0 0", 1000),
+        ]);
+        history.push(vec![core_snapshot("cpu 50 0 0 0 0 0 0 0 0 0", 1000), core_snapshot("cpu1 50 0 0 0 0 0 0 0 0 0", 1000)]);
+        history.push(vec![
+            core_snapshot("cpu2 70 0 0 0 0 0 0 0 0 0", 1000),
+            core_snapshot("cpu0 10 0 0 0 0 0 0 0 0 0", 1000),
+            core_snapshot("cpu1 30 0 0 0 0 0 0 0 0 0", 1000),
+        ]);
+
+        let matrix = history.to_matrix();
+        assert_eq!(matrix.len(), 3);
+        assert!(matrix.iter().all(|row| row.len() == 3));
+        assert_eq!(matrix[0][..2], [40.0, 20.0]);
+        assert!(matrix[0][2].is_nan());
+        assert!(matrix[1][0].is_nan());
+        assert_eq!(matrix[1][1], 50.0);
+        assert_eq!(matrix[2], vec![10.0, 30.0, 70.0]);
+
+        assert!(History::new(3).to_matrix().is_empty());
+    }
+
+    #[test]
+    fn windowed_usage() {
+        let start = Instant::now();
+        let at = |seconds| start + Duration::from_secs(seconds);
+        let mut windowed = WindowedUsage::new(Duration::from_secs(5));
+        assert_eq!(windowed.usage_over(Duration::from_secs(5)), None);
+
+        windowed.push(at(0), core_snapshot("cpu0 90 0 0 0 0 0 0 0 0 0", 1000));
+        windowed.push(at(2), core_snapshot("cpu0 90 0 0 0 0 0 0 0 0 0", 1000));
+        windowed.push(at(4), core_snapshot("cpu0 10 0 0 0 0 0 0 0 0 0", 1000));
+        windowed.push(at(6), core_snapshot("cpu0 20 0 0 0 0 0 0 0 0 0", 1000));
+        windowed.push(at(7), core_snapshot("cpu0 30 0 0 0 0 0 0 0 0 0", 1000));
+
+        assert_eq!(windowed.len(), 4);
+        assert_eq!(windowed.usage_over(Duration::from_secs(5)), Some(37.5));
+        assert_eq!(windowed.usage_over(Duration::from_secs(1)), Some(25.0));
+        assert_eq!(windowed.usage_over(Duration::from_secs(60)), Some(37.5));
+
+        let mut saturated = WindowedUsage::new(Duration::from_secs(5));
+        saturated.push(at(0), core_snapshot(&format!("cpu0 {} 0 0 0 0 0 0 0 0 0", u64::MAX), 1000));
+        saturated.push(at(1), core_snapshot("cpu0 1 0 0 0 0 0 0 0 0 0", 1000));
+        assert_eq!(saturated.usage_over(Duration::from_secs(5)), Some(u64::MAX as f64 / 2.0));
+    }
+}
@@ -0,0 +1,201 @@
+///
+/// Prometheus metrics endpoint
+///
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread;
+use std::time::Duration;
+
+use tiny_http::{Header, Response, Server};
+
+use crate::{CPUStatsContext, Snapshot};
+
+///
+/// HTTP server publishing CPU usage at `/metrics` in the Prometheus text format
+///
+pub struct MetricsServer {
+    server: Server,
+    metrics: Arc<Mutex<String>>,
+}
+
+impl MetricsServer {
+    ///
+    /// Bind to the address and start sampling `/proc/stat` every `interval`.
+    ///
+    pub fn bind<A: ToSocketAddrs>(addr: A, interval: Duration) -> io::Result<Self> {
+        MetricsServer::with_context(addr, CPUStatsContext::new()?, interval)
+    }
+
+    ///
+    /// Bind to the address and sample the context every `interval`.
+    ///
+    /// If the sampler stops, eg a read failed, the metrics are cleared so stale values are
+    /// not served.
+    ///
+    fn with_context<A: ToSocketAddrs>(addr: A, context: CPUStatsContext, interval: Duration) -> io::Result<Self> {
+        let server = Server::http(addr).map_err(io::Error::other)?;
+        let (sampler, receiver) = context.spawn_sampler(interval);
+
+        let metrics = Arc::new(Mutex::new(String::new()));
+        let latest = Arc::clone(&metrics);
+        thread::spawn(move || {
+            for snapshots in receiver {
+                let text = Snapshot::new(snapshots).to_prometheus();
+                *latest.lock().unwrap_or_else(PoisonError::into_inner) = text;
+            }
+            latest.lock().unwrap_or_else(PoisonError::into_inner).clear();
+
+            let result = sampler.join();
+            #[cfg(feature = "log")]
+            match result {
+                Ok(Err(error)) => log::error!("metrics sampler stopped: {}", error),
+                Err(_) => log::error!("metrics sampler panicked"),
+                Ok(Ok(())) => {}
+            }
+            #[cfg(not(feature = "log"))]
+            drop(result);
+        });
+
+        Ok(Self { server, metrics })
+    }
+
+    ///
+    /// Address the server is listening on
+    ///
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.server.server_addr().to_ip()
+    }
+
+    ///
+    /// Serve requests until the process exits.
+    ///
+    /// `/metrics` returns 503 until the first sample has been taken or if sampling has stopped,
+    /// other paths return 404. A response that can not be sent, eg the client disconnected,
+    /// does not stop the server.
+    ///
+    pub fn run(self) -> io::Result<()> {
+        let content_type = Header::from_bytes("Content-Type", "text/plain; version=0.0.4")
+            .expect("valid header");
+        for request in self.server.incoming_requests() {
+            let response = if request.url() == "/metrics" {
+                let text = self.metrics.lock().unwrap_or_else(PoisonError::into_inner).clone();
+                if text.is_empty() {
+                    Response::from_string("metrics unavailable").with_status_code(503)
+                } else {
+                    Response::from_string(text).with_header(content_type.clone())
+                }
+            } else {
+                Response::from_string("not found").with_status_code(404)
+            };
+            let result = request.respond(response);
+            #[cfg(feature = "log")]
+            if let Err(error) = result {
+                log::warn!("failed to send metrics response: {}", error);
+            }
+            #[cfg(not(feature = "log"))]
+            drop(result);
+        }
+        Ok(())
+    }
+}
+
+///
+/// Sample CPU usage every `interval` and serve it at `/metrics` on `addr`, blocking forever.
+///
+pub fn serve_metrics<A: ToSocketAddrs>(addr: A, interval: Duration) -> io::Result<()> {
+    MetricsServer::bind(addr, interval)?.run()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+    use super::*;
+    use crate::StatsSource;
+
+    fn get(addr: SocketAddr, path: &str) -> String {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(stream, "GET {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n", path).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn serves_metrics() {
+        let server = MetricsServer::bind("127.0.0.1:0", Duration::from_millis(10)).unwrap();
+        let addr = server.local_addr().unwrap();
+        thread::spawn(move || server.run());
+
+        let mut response = String::new();
+        for _ in 0..100 {
+            response = get(addr, "/metrics");
+            if response.contains("cpu_usage_percent{") { break }
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+        assert!(response.contains("cpu_usage_percent{cpu=\"cpu\"}"), "{}", response);
+        assert!(get(addr, "/other").starts_with("HTTP/1.1 404"));
+    }
+
+    #[test]
+    fn survives_dropped_connection() {
+        let server = MetricsServer::bind("127.0.0.1:0", Duration::from_millis(10)).unwrap();
+        let addr = server.local_addr().unwrap();
+        thread::spawn(move || server.run());
+
+        // Pipeline requests and close without reading, the responses after the first are
+        // rejected by the closed socket
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let requests = "GET /other HTTP/1.1\r\nHost: localhost\r\n\r\n".repeat(50);
+        stream.write_all(requests.as_bytes()).unwrap();
+        drop(stream);
+        thread::sleep(Duration::from_millis(100));
+
+        let response = get(addr, "/other");
+        assert!(response.starts_with("HTTP/1.1 404"), "{}", response);
+    }
+
+    ///
+    /// Source that fails after the first two reads
+    ///
+    struct Failing {
+        reads: usize,
+    }
+
+    impl StatsSource for Failing {
+        fn read_stats(&mut self) -> io::Result<String> {
+            self.reads += 1;
+            match self.reads {
+                1 | 2 => Ok("cpu  1 0 0 1 0 0 0 0 0 0\n".to_string()),
+                _ => Err(io::Error::other("stats unavailable")),
+            }
+        }
+    }
+
+    #[test]
+    fn unavailable_after_sampler_fails() {
+        let context = CPUStatsContext::from_source(Failing { reads: 0 }).unwrap();
+        let server = MetricsServer::with_context("127.0.0.1:0", context, Duration::from_millis(200)).unwrap();
+        let addr = server.local_addr().unwrap();
+        thread::spawn(move || server.run());
+
+        let mut response = String::new();
+        for _ in 0..100 {
+            response = get(addr, "/metrics");
+            if response.starts_with("HTTP/1.1 200") { break }
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert!(response.contains("cpu_usage_percent{cpu=\"cpu\"}"), "{}", response);
+
+        for _ in 0..100 {
+            response = get(addr, "/metrics");
+            if response.starts_with("HTTP/1.1 503") { break }
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        assert!(response.starts_with("HTTP/1.1 503"), "{}", response);
+    }
+}
@@ -1,21 +1,134 @@
 ///
 /// Read CPU statistics from proc file system
 ///
-use std::time::Instant;
+use std::time::{Duration, Instant};
 use std::io;
-use std::io::BufRead;
-use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc, Mutex, PoisonError, TryLockError};
+use std::thread::{self, JoinHandle};
 use std::fmt::{Display, Formatter};
+use std::collections::{BTreeSet, HashMap};
+use std::convert::TryFrom;
+use std::str::FromStr;
+use std::cmp::Ordering;
+
+#[cfg(target_os = "linux")]
+mod affinity;
+mod alert;
+mod breakdown;
+mod clock;
+mod compare;
+mod cpufreq;
+mod error;
+mod history;
+#[cfg(feature = "http")]
+mod http;
+mod numa;
+#[cfg(feature = "otel")]
+mod otel;
+pub mod prelude;
+mod rates;
+mod record;
+mod report;
+mod snapshot;
+mod source;
+mod system;
+#[cfg(feature = "sysinfo-compat")]
+mod sysinfo_compat;
+
+pub use alert::AlertState;
+pub use breakdown::Breakdown;
+pub use clock::{Clock, SystemClock};
+pub use compare::{compare, CoreComparison};
+pub use error::CpuStatsError;
+pub use history::{History, UsageAggregate, WindowedUsage};
+#[cfg(feature = "http")]
+pub use http::{serve_metrics, MetricsServer};
+#[cfg(feature = "otel")]
+pub use otel::CORE_ATTRIBUTE;
+pub use numa::{parse_cpu_list, read_numa_nodes};
+pub use rates::CoreRates;
+pub use record::RECORD_SIZE;
+pub use report::{CoreReport, SnapshotReport};
+pub use snapshot::{Snapshot, StateThresholds, SystemState};
+pub use source::{OpenStatsFile, StatsFile, StatsSource};
+pub use system::SystemStats;
+#[cfg(feature = "sysinfo-compat")]
+pub use sysinfo_compat::Cpu;
 
 const STATS_FILE: &str = "/proc/stat";
+const SYSFS_ROOT: &str = "/sys";
+
+///
+/// Context switches per core per second treated as full pressure by `context_switch_pressure`
+///
+const HIGH_SWITCH_RATE: f64 = 10_000.0;
+
+///
+/// Change in system usage, in percentage points, below which `adaptive_sample` lengthens
+/// the interval
+///
+const STABLE_USAGE_CHANGE: f64 = 5.0;
+
+///
+/// Change in system usage, in percentage points, above which `adaptive_sample` shortens
+/// the interval
+///
+const VOLATILE_USAGE_CHANGE: f64 = 20.0;
+
+///
+/// Typical clock tick (USER_HZ) the kernel reports times in, ticks per second
+///
+pub const USER_HZ: u64 = 100;
+
+///
+/// Convert a count of jiffies to real time given the clock tick (ticks per second).
+///
+/// Zero if the clock tick is zero.
+///
+pub fn jiffies_to_duration(jiffies: u64, clock_tick: u64) -> Duration {
+    if clock_tick == 0 {
+        return Duration::from_secs(0);
+    }
+    let nanos = jiffies as u128 * 1_000_000_000 / clock_tick as u128;
+    Duration::new((nanos / 1_000_000_000) as u64, (nanos % 1_000_000_000) as u32)
+}
+
+///
+/// Convert a per-core percentage, where each core counts 100, to a whole system percentage.
+///
+/// eg 100% of one core on a 4 core system is 25% of the system. `None` if there are no cores.
+///
+pub fn per_core_to_system(percent: f64, core_count: usize) -> Option<f64> {
+    match core_count {
+        0 => None,
+        count => Some(percent / count as f64),
+    }
+}
+
+///
+/// Convert a whole system percentage to the per-core convention, the inverse of
+/// `per_core_to_system`. `None` if there are no cores.
+///
+pub fn system_to_per_core(percent: f64, core_count: usize) -> Option<f64> {
+    match core_count {
+        0 => None,
+        count => Some(percent * count as f64),
+    }
+}
 
 ///
 /// Statistics for a single CPU core, all counts are aggregates since system boot.
 ///
 /// Times are measured in USER_HZ or Jiffies (typically hundredths of a second).
 ///
+/// Arithmetic on counters saturates rather than overflowing, differences where a counter went
+/// backwards (eg I/O wait, or a reset) are zero and sums are capped at `u64::MAX`.
+///
 /// For more information see: https://www.kernel.org/doc/html/latest/filesystems/proc.html#miscellaneous-kernel-statistics-in-proc-stat
 ///
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct CoreStats {
     ///
     /// Name of core
@@ -75,38 +188,71 @@ pub struct CoreStats {
 }
 
 macro_rules! next_value {
-    ($iter:expr, $type:ty) => {
-        $iter.next().and_then(|word| word.parse::<$type>().ok())
+    ($iter:expr, $type:ty, $field:expr) => {
+        $iter.next()
+            .ok_or_else(|| CpuStatsError::Parse(format!("missing {} field", $field)))
+            .and_then(|word| word.parse::<$type>().map_err(|_| {
+                CpuStatsError::Parse(format!("invalid {} field `{}`", $field, word))
+            }))
     }
 }
 
+///
+/// Field that older or unusual kernels may leave off the end of the line, zero if absent
+///
+macro_rules! optional_value {
+    ($iter:expr, $type:ty, $field:expr) => {
+        match $iter.next() {
+            Some(word) => word.parse::<$type>().map_err(|_| {
+                CpuStatsError::Parse(format!("invalid {} field `{}`", $field, word))
+            }),
+            None => Ok(0),
+        }
+    }
+}
 
-impl CoreStats {
-    fn from_str(line: &str) -> Option<Self> {
-        let mut atoms = line.split(" ");
+impl FromStr for CoreStats {
+    type Err = CpuStatsError;
 
-        let name = atoms.next()?;
-        // If the first item is just 'cpu' it's the aggregate of all cores
-        // and the next item will be a blank entry
-        if name == "cpu" {
-            atoms.next()?;
-        }
+    ///
+    /// Parse a single `cpu` line from the stats file.
+    ///
+    /// Only user, nice, system and idle are required. The fields after them appeared in later
+    /// kernels and any missing from the end of the line are zero.
+    ///
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let mut atoms = line.split_whitespace();
 
-        Some(Self {
+        let name = match atoms.next() {
+            Some(name) if name.starts_with("cpu") => name,
+            _ => return Err(CpuStatsError::Parse(format!("not a cpu line `{}`", line))),
+        };
+
+        Ok(Self {
             name: String::from(name),
-            user_processes: next_value!(atoms, u64)?,
-            nice_processes: next_value!(atoms, u64)?,
-            system_processes: next_value!(atoms, u64)?,
-            idle_time: next_value!(atoms, u64)?,
-            io_wait: next_value!(atoms, u64)?,
-            irq: next_value!(atoms, u64)?,
-            soft_irq: next_value!(atoms, u64)?,
-            steal_time: next_value!(atoms, u64)?,
-            guest: next_value!(atoms, u64)?,
-            guest_nice: next_value!(atoms, u64)?,
+            user_processes: next_value!(atoms, u64, "user")?,
+            nice_processes: next_value!(atoms, u64, "nice")?,
+            system_processes: next_value!(atoms, u64, "system")?,
+            idle_time: next_value!(atoms, u64, "idle")?,
+            io_wait: optional_value!(atoms, u64, "iowait")?,
+            irq: optional_value!(atoms, u64, "irq")?,
+            soft_irq: optional_value!(atoms, u64, "softirq")?,
+            steal_time: optional_value!(atoms, u64, "steal")?,
+            guest: optional_value!(atoms, u64, "guest")?,
+            guest_nice: optional_value!(atoms, u64, "guest_nice")?,
         })
     }
+}
+
+impl TryFrom<&str> for CoreStats {
+    type Error = CpuStatsError;
+
+    fn try_from(line: &str) -> Result<Self, Self::Error> {
+        line.parse()
+    }
+}
 
+impl CoreStats {
     ///
     /// Is the aggregate of all cores
     ///
@@ -114,115 +260,2516 @@ impl CoreStats {
         self.name == "cpu"
     }
 
+    ///
+    /// Time spent doing work, ie everything except idle and I/O wait.
+    ///
+    /// Guest time is already included in user time so is not counted again.
+    ///
+    pub fn active_time(&self) -> u64 {
+        self.user_processes
+            .saturating_add(self.nice_processes)
+            .saturating_add(self.system_processes)
+            .saturating_add(self.irq)
+            .saturating_add(self.soft_irq)
+            .saturating_add(self.steal_time)
+    }
+
+    ///
+    /// Numeric index parsed from the core name, `None` for the aggregate
+    ///
+    pub fn index(&self) -> Option<usize> {
+        self.name.strip_prefix("cpu").and_then(|index| index.parse().ok())
+    }
+
+    ///
+    /// Idle time as real time given the clock tick, see `USER_HZ`.
+    ///
+    /// For a delta this is the time the core was idle during the period.
+    ///
+    pub fn idle_duration(&self, clock_tick: u64) -> Duration {
+        jiffies_to_duration(self.idle_time, clock_tick)
+    }
+
+    ///
+    /// Labelled time categories in the order they appear in the stats file.
+    ///
+    pub fn fields(&self) -> impl Iterator<Item = (&'static str, u64)> {
+        IntoIterator::into_iter([
+            ("user", self.user_processes),
+            ("nice", self.nice_processes),
+            ("system", self.system_processes),
+            ("idle", self.idle_time),
+            ("iowait", self.io_wait),
+            ("irq", self.irq),
+            ("softirq", self.soft_irq),
+            ("steal", self.steal_time),
+            ("guest", self.guest),
+            ("guest_nice", self.guest_nice),
+        ])
+    }
+
+    ///
+    /// Sum the counters of stats for the same core captured from several sources.
+    ///
+    /// `None` if there are no stats or they are not all for the same named core.
+    ///
+    pub fn merge(others: &[CoreStats]) -> Option<CoreStats> {
+        let (first, rest) = others.split_first()?;
+        rest.iter().try_fold(first.clone(), |mut merged, other| {
+            if other.name != merged.name {
+                return None;
+            }
+            merged.user_processes = merged.user_processes.saturating_add(other.user_processes);
+            merged.nice_processes = merged.nice_processes.saturating_add(other.nice_processes);
+            merged.system_processes = merged.system_processes.saturating_add(other.system_processes);
+            merged.idle_time = merged.idle_time.saturating_add(other.idle_time);
+            merged.io_wait = merged.io_wait.saturating_add(other.io_wait);
+            merged.irq = merged.irq.saturating_add(other.irq);
+            merged.soft_irq = merged.soft_irq.saturating_add(other.soft_irq);
+            merged.steal_time = merged.steal_time.saturating_add(other.steal_time);
+            merged.guest = merged.guest.saturating_add(other.guest);
+            merged.guest_nice = merged.guest_nice.saturating_add(other.guest_nice);
+            Some(merged)
+        })
+    }
+
+    ///
+    /// Change in each counter since an earlier reading of the same core
+    ///
+    pub fn delta_since(&self, earlier: &CoreStats) -> CoreDelta {
+        earlier.diff(self)
+    }
+
+    ///
+    /// Move these counters on to a later reading of the same core, returning the change.
+    ///
+    /// The name of `self` is kept, the delta takes ownership of the name from `later`.
+    ///
+    fn advance(&mut self, mut later: CoreStats) -> CoreDelta {
+        let name = std::mem::replace(&mut later.name, std::mem::take(&mut self.name));
+        let delta = self.diff_named(&later, name);
+        *self = later;
+        delta
+    }
+
     fn diff(&self, other: &Self) -> Self {
+        self.diff_named(other, self.name.clone())
+    }
+
+    fn diff_named(&self, other: &Self, name: String) -> Self {
         Self {
-            name: self.name.clone(),
-            user_processes: other.user_processes - self.user_processes,
-            nice_processes: other.nice_processes - self.nice_processes,
-            system_processes: other.system_processes - self.system_processes,
-            idle_time: other.idle_time - self.idle_time,
-            io_wait: other.io_wait,
-            irq: other.irq - self.irq,
-            soft_irq: other.soft_irq - self.soft_irq,
-            steal_time: other.steal_time - self.steal_time,
-            guest: other.guest - self.guest,
-            guest_nice: other.guest_nice - self.guest_nice,
+            name,
+            user_processes: other.user_processes.saturating_sub(self.user_processes),
+            nice_processes: other.nice_processes.saturating_sub(self.nice_processes),
+            system_processes: other.system_processes.saturating_sub(self.system_processes),
+            idle_time: other.idle_time.saturating_sub(self.idle_time),
+            io_wait: other.io_wait.saturating_sub(self.io_wait),
+            irq: other.irq.saturating_sub(self.irq),
+            soft_irq: other.soft_irq.saturating_sub(self.soft_irq),
+            steal_time: other.steal_time.saturating_sub(self.steal_time),
+            guest: other.guest.saturating_sub(self.guest),
+            guest_nice: other.guest_nice.saturating_sub(self.guest_nice),
         }
     }
 }
 
+///
+/// How fractional percentages are converted to whole numbers
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RoundingMode {
+    ///
+    /// Round down, matching the truncation of `idle_percent`
+    ///
+    #[default]
+    Floor,
+    ///
+    /// Round to nearest, halves away from zero
+    ///
+    Round,
+    ///
+    /// Round up
+    ///
+    Ceil,
+}
+
+impl RoundingMode {
+    ///
+    /// Convert a percentage to a whole number, negative values become zero
+    ///
+    pub fn apply(self, percent: f64) -> u64 {
+        let rounded = match self {
+            RoundingMode::Floor => percent.floor(),
+            RoundingMode::Round => percent.round(),
+            RoundingMode::Ceil => percent.ceil(),
+        };
+        rounded.max(0.0) as u64
+    }
+}
+
+///
+/// Change in core statistics over a period, same layout as the absolute counters.
+///
+/// Each field is the number of jiffies spent in that state during the period, for building
+/// metrics not covered by the percentage methods of `CoreSnapshot`.
+///
+pub type CoreDelta = CoreStats;
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct CoreSnapshot {
-    pub stats: CoreStats,
-    pub period_ms: u64,
+    pub stats: CoreDelta,
+    ///
+    /// Time between the reads the deltas were calculated from, may be zero
+    ///
+    period: Duration,
+    ///
+    /// Usage of the same core in the previous read, when tracked by the context
+    ///
+    previous_usage: Option<f64>,
 }
 
 impl CoreSnapshot {
+    pub fn new(stats: CoreDelta, period: Duration) -> Self {
+        Self {
+            stats,
+            period,
+            previous_usage: None,
+        }
+    }
+
+    ///
+    /// Change in each counter over the period
+    ///
+    pub fn delta(&self) -> &CoreDelta {
+        &self.stats
+    }
+
+    ///
+    /// Is the aggregate of all cores
+    ///
+    pub fn is_aggregate(&self) -> bool {
+        self.stats.is_aggregate()
+    }
+
+    ///
+    /// Numeric core index for use as a cheap map key, `-1` for the aggregate.
+    ///
+    pub fn core_id(&self) -> i32 {
+        self.stats.index().map_or(-1, |index| index as i32)
+    }
+
+    ///
+    /// Time period covered by the snapshot
     ///
-    /// Percentage of last time period spent idle.
+    pub fn period(&self) -> Duration {
+        self.period
+    }
+
+    ///
+    /// Time period covered by the snapshot in whole milliseconds
+    ///
+    pub fn period_ms(&self) -> u64 {
+        saturate(self.period.as_millis())
+    }
+
+    ///
+    /// Jiffies a single core can accumulate over the period at the given clock tick.
+    ///
+    /// Comparing this to the sum of the core's counters shows over or under accounting.
+    ///
+    pub fn expected_jiffies(&self, clock_tick: u64) -> u64 {
+        saturate(self.period.as_nanos() * clock_tick as u128 / 1_000_000_000)
+    }
+
+    ///
+    /// Percentage of last time period spent idle, zero if the period is zero.
     ///
     /// Note for the aggregate this value will be greater than 100.
     ///
     pub fn idle_percent(&self) -> u64 {
-        (self.stats.idle_time * 1000) / self.period_ms
+        self.checked_idle_percent().unwrap_or(0)
     }
-}
 
-impl Display for CoreSnapshot {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}: {:3}%", self.stats.name, self.idle_percent())
+    ///
+    /// Idle percentage of the whole system (0-100), dividing the aggregate by the core count.
+    ///
+    /// Individual cores are already 0-100 and are not divided. Zero if the period or core
+    /// count is zero.
+    ///
+    pub fn normalized_idle_percent(&self, core_count: usize) -> f64 {
+        let idle = self.percent_of_period(self.stats.idle_time);
+        match core_count {
+            0 => 0.0,
+            _ if self.is_aggregate() => idle / core_count as f64,
+            _ => idle,
+        }
     }
-}
 
-///
-/// Context for reading CPU statistics
-///
-/// Includes methods for getting snapshots between reads
-///
-pub struct CPUStatsContext {
     ///
-    /// Stats from the previous read
+    /// Change in idle percentage, in percentage points, since an earlier snapshot of the same
+    /// core, eg -20 when idle dropped from 70% to 50%.
     ///
-    pub last_stats: Vec<CoreStats>,
+    /// `None` if the snapshots are of different cores.
     ///
-    /// Instant when the stats where last read
+    pub fn idle_delta_pp(&self, previous: &CoreSnapshot) -> Option<f64> {
+        if self.stats.name != previous.stats.name {
+            return None;
+        }
+        Some(self.percent_of_period(self.stats.idle_time) - previous.percent_of_period(previous.stats.idle_time))
+    }
+
     ///
-    last_instant: Instant,
-}
+    /// Percentage of last time period spent idle, or `None` if the period is zero.
+    ///
+    pub fn checked_idle_percent(&self) -> Option<u64> {
+        (self.stats.idle_time as u128 * 1_000_000_000).checked_div(self.period.as_nanos()).map(saturate)
+    }
 
-impl CPUStatsContext {
-    pub fn new() -> io::Result<Self> {
-        let now = Instant::now();
-        Ok(Self {
-            last_stats: CPUStatsContext::raw_read()?,
-            last_instant: now,
-        })
+    ///
+    /// Idle percentage as a whole number using the given rounding mode, zero if the period is
+    /// zero.
+    ///
+    pub fn idle_percent_rounded(&self, mode: RoundingMode) -> u64 {
+        mode.apply(self.percent_of_period(self.stats.idle_time))
     }
 
     ///
-    /// Read stats and generate performance snapshot.
+    /// Usage percentage as a whole number using the given rounding mode.
     ///
-    pub fn read(&mut self) -> io::Result<Vec<CoreSnapshot>> {
-        let now = Instant::now();
-        let period_ms = self.last_instant.elapsed().as_millis() as u64;
-        let now_stats = CPUStatsContext::raw_read()?;
-
-        let snapshots = self.last_stats.iter().zip(&now_stats).map(|(l, n)| {
-            CoreSnapshot {
-                stats: l.diff(&n),
-                period_ms
-            }
-        }).collect();
+    pub fn usage_percent_rounded(&self, mode: RoundingMode) -> u64 {
+        mode.apply(self.usage_percent())
+    }
 
-        self.last_instant = now;
-        self.last_stats = now_stats;
+    ///
+    /// Percentage of last time period spent doing work, zero if the period is zero.
+    ///
+    /// As with `idle_percent` the aggregate can be greater than 100.
+    ///
+    pub fn usage_percent(&self) -> f64 {
+        self.percent_of_period(self.stats.active_time())
+    }
 
-        Ok(snapshots)
+    ///
+    /// `usage_percent` as an `f32`, for compact time series.
+    ///
+    pub fn usage_percent_f32(&self) -> f32 {
+        self.usage_percent() as f32
     }
 
     ///
-    /// Read raw core stats
+    /// Usage with niced time counted at `nice_weight`, eg 0.5 counts niced work at half.
     ///
-    fn raw_read() -> io::Result<Vec<CoreStats>> {
-        let file = File::open(STATS_FILE)?;
+    /// A weight of 1 gives the same result as `usage_percent`, 0 ignores niced work.
+    ///
+    pub fn weighted_usage(&self, nice_weight: f64) -> f64 {
+        let unniced = self.stats.active_time().saturating_sub(self.stats.nice_processes);
+        let nice = self.stats.nice_processes as f64 * nice_weight;
+        self.percent_of_period(unniced) + self.percent_of_period(1) * nice
+    }
 
-        let mut cores: Vec<CoreStats> = Vec::new();
-        for line in io::BufReader::new(file).lines() {
-            let line = line?;
-            if !line.starts_with("cpu") { continue }
-            if let Some(core) = CoreStats::from_str(line.as_str()) {
-                cores.push(core);
-            }
+    ///
+    /// Percentage of last time period spent on computation, user, nice, system, irq and
+    /// soft-irq time.
+    ///
+    /// Unlike `usage_percent` steal time is excluded so only work done by this core counts.
+    ///
+    pub fn compute_percent(&self) -> f64 {
+        let stats = &self.stats;
+        self.percent_of_period(
+            stats.user_processes
+                .saturating_add(stats.nice_processes)
+                .saturating_add(stats.system_processes)
+                .saturating_add(stats.irq)
+                .saturating_add(stats.soft_irq)
+        )
+    }
+
+    ///
+    /// Jiffies as a percentage of the period, zero if the period is zero
+    ///
+    fn percent_of_period(&self, jiffies: u64) -> f64 {
+        let period_ms = self.period.as_secs_f64() * 1000.0;
+        if period_ms == 0.0 {
+            return 0.0;
+        }
+        jiffies as f64 * 1000.0 / period_ms
+    }
+
+    ///
+    /// Change in usage since the previous read of the same core.
+    ///
+    /// Only available when the context is tracking usage changes and the core was present in
+    /// the previous read.
+    ///
+    pub fn usage_change(&self) -> Option<f64> {
+        self.previous_usage.map(|previous| self.usage_percent() - previous)
+    }
+
+    ///
+    /// Jiffies stolen by the hypervisor during the last period.
+    ///
+    /// Steal is time this virtual CPU was runnable but the hypervisor was running something
+    /// else, it is unrelated to guest time (time this CPU spent running guests of its own).
+    /// How promptly it is reported varies between hypervisors so treat small values with
+    /// caution.
+    ///
+    pub fn steal_jiffies(&self) -> u64 {
+        self.stats.steal_time
+    }
+
+    ///
+    /// Ratio of user to system time over the last period, `None` if there was no system time.
+    ///
+    /// Values well above one indicate a userspace bound workload, below one kernel bound.
+    ///
+    pub fn user_system_ratio(&self) -> Option<f64> {
+        match self.stats.system_processes {
+            0 => None,
+            system => Some(self.stats.user_processes as f64 / system as f64),
+        }
+    }
+
+    ///
+    /// Busiest time category during the last period along with its percentage.
+    ///
+    /// Only the categories making up `active_time` are considered, so idle, I/O wait and
+    /// guest time (already counted in user) are excluded. Ties go to the earlier category.
+    ///
+    pub fn dominant_category(&self) -> (&'static str, f64) {
+        let (name, jiffies) = self.stats.fields()
+            .filter(|(name, _)| !matches!(*name, "idle" | "iowait" | "guest" | "guest_nice"))
+            .fold(("user", 0), |best, field| if field.1 > best.1 { field } else { best });
+
+        (name, self.percent_of_period(jiffies))
+    }
+
+    ///
+    /// Usage rounded to a whole percentage and clamped to 0-100 for compact storage.
+    ///
+    /// The aggregate is clamped to 100, normalise it first if per-system usage is wanted.
+    ///
+    pub fn usage_percent_u8(&self) -> u8 {
+        self.usage_percent().round().clamp(0.0, 100.0) as u8
+    }
+
+    ///
+    /// Usage scaled by the core's current frequency as a fraction of its maximum.
+    ///
+    /// 20% usage at half the maximum frequency is 10% of the core's capacity. Zero if
+    /// the maximum frequency is zero.
+    ///
+    pub fn effective_utilization(&self, cur_khz: u64, max_khz: u64) -> f64 {
+        if max_khz == 0 {
+            return 0.0;
+        }
+        self.usage_percent() * (cur_khz as f64 / max_khz as f64)
+    }
+
+    ///
+    /// Unitless estimate of relative power draw, for comparing cores rather than measuring.
+    ///
+    /// Dynamic power scales roughly with frequency times voltage squared and voltage tends to
+    /// track frequency, so the estimate is the fraction of the period busy times the cube of the
+    /// frequency ratio, scaled by the core's share of the package TDP. A fully busy core at its
+    /// maximum frequency scores `tdp_fraction`. Zero if the maximum frequency is zero.
+    ///
+    pub fn power_proxy(&self, cur_khz: u64, max_khz: u64, tdp_fraction: f64) -> f64 {
+        if max_khz == 0 {
+            return 0.0;
         }
-        Ok(cores)
+        let ratio = cur_khz as f64 / max_khz as f64;
+        self.usage_percent() / 100.0 * ratio.powi(3) * tdp_fraction
     }
 }
 
-#[cfg(test)]
-mod tests {
-    #[test]
-    fn refresh_stats() {
-        let mut stats_context = super::CPUStatsContext::new().unwrap();
+///
+/// Narrow a wide intermediate value, saturating at `u64::MAX`
+///
+fn saturate(value: u128) -> u64 {
+    value.min(u64::MAX as u128) as u64
+}
 
-        assert!(stats_context.read().is_ok())
+///
+/// Name and idle percent, the alternate form `{:#}` adds the period eg `cpu0:  63% over 1002ms`.
+///
+impl Display for CoreSnapshot {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {:3}%", self.stats.name, self.checked_idle_percent().unwrap_or(0))?;
+        if f.alternate() {
+            write!(f, " over {}ms", self.period_ms())?;
+        }
+        Ok(())
+    }
+}
+
+impl Eq for CoreSnapshot {}
+
+impl PartialOrd for CoreSnapshot {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+///
+/// Snapshots are ordered by `usage_percent`, ties are broken by core name.
+///
+/// The remaining counters only order otherwise identical snapshots, keeping the ordering
+/// consistent with equality.
+///
+impl Ord for CoreSnapshot {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.usage_percent().total_cmp(&other.usage_percent())
+            .then_with(|| self.stats.name.cmp(&other.stats.name))
+            .then_with(|| self.stats.fields().map(|(_, value)| value).cmp(other.stats.fields().map(|(_, value)| value)))
+            .then_with(|| self.period.cmp(&other.period))
+            .then_with(|| match (self.previous_usage, other.previous_usage) {
+                (Some(a), Some(b)) => a.total_cmp(&b),
+                (a, b) => a.is_some().cmp(&b.is_some()),
+            })
+    }
+}
+
+///
+/// Context for reading CPU statistics
+///
+/// Includes methods for getting snapshots between reads. Snapshots from `read` are windowed,
+/// they hold the change in each counter since the previous read. `raw_snapshot` instead
+/// returns the instantaneous absolute counters for callers managing their own deltas.
+///
+pub struct CPUStatsContext {
+    ///
+    /// Stats from the previous read
+    ///
+    pub last_stats: Vec<CoreStats>,
+    ///
+    /// System wide stats from the last read
+    ///
+    last_system: SystemStats,
+    ///
+    /// System wide stats from the read before last, used to calculate rates
+    ///
+    previous_system: SystemStats,
+    ///
+    /// Instant when the stats where last read
+    ///
+    last_instant: Instant,
+    ///
+    /// Period covered by the last read
+    ///
+    last_period: Duration,
+    ///
+    /// Time taken by the last read to read and process the stats
+    ///
+    last_read_cost: Duration,
+    ///
+    /// Where stats are read from
+    ///
+    source: Arc<Mutex<dyn StatsSource>>,
+    ///
+    /// Clock used to measure periods
+    ///
+    clock: Box<dyn Clock>,
+    ///
+    /// Maximum time to wait for the source to respond
+    ///
+    timeout: Option<Duration>,
+    ///
+    /// Indexes of the cores to track, all cores if not set
+    ///
+    allowed_cores: Option<BTreeSet<usize>>,
+    ///
+    /// Usage of each core in the last read, when tracking usage changes
+    ///
+    last_usage: Option<HashMap<String, f64>>,
+    ///
+    /// Options controlling how the stats file is parsed
+    ///
+    options: ReadOptions,
+    ///
+    /// Where sysfs is mounted, for core frequencies
+    ///
+    sysfs_root: PathBuf,
+    ///
+    /// Include the aggregate of all cores in the output of `read`
+    ///
+    include_aggregate: bool,
+    ///
+    /// Highest usage of each core seen by any read
+    ///
+    peak_usage: HashMap<String, f64>,
+    ///
+    /// Position of each core in `last_stats` by name
+    ///
+    core_index: HashMap<String, usize>,
+    ///
+    /// Counters and instant marked by `mark_baseline`
+    ///
+    baseline: Option<(Vec<CoreStats>, Instant)>,
+    ///
+    /// Steal time was seen in a previous baseline
+    ///
+    steal_seen: bool,
+    ///
+    /// Interval and system usage of the last `adaptive_sample`
+    ///
+    adaptive: Option<(Duration, Option<f64>)>,
+    ///
+    /// A snapshot has been produced since construction
+    ///
+    has_read: bool,
+}
+
+///
+/// Options controlling how the stats file is parsed
+///
+#[derive(Debug, Clone, Copy, Default)]
+struct ReadOptions {
+    ///
+    /// Stop after the aggregate line
+    ///
+    aggregate_only: bool,
+    ///
+    /// Drop the aggregate line, keeping only the individual cores
+    ///
+    per_core_only: bool,
+    ///
+    /// Error on malformed or inconsistent core lines rather than skipping them
+    ///
+    strict: bool,
+    ///
+    /// Parse the individual IRQ counts from the intr line
+    ///
+    per_irq: bool,
+}
+
+impl CPUStatsContext {
+    pub fn new() -> io::Result<Self> {
+        CPUStatsContext::from_source(StatsFile::default())
+    }
+
+    ///
+    /// Context reading stats from a file in `/proc/stat` format, eg a captured copy.
+    ///
+    pub fn from_path<P: Into<PathBuf>>(path: P) -> io::Result<Self> {
+        CPUStatsContext::from_source(StatsFile::new(path))
+    }
+
+    ///
+    /// Context reading stats from the supplied source rather than `/proc/stat`.
+    ///
+    pub fn from_source<S: StatsSource + 'static>(source: S) -> io::Result<Self> {
+        CPUStatsContext::with_options(Arc::new(Mutex::new(source)), ReadOptions::default())
+    }
+
+    ///
+    /// Context reading stats through a file descriptor opened by the caller, eg passed in to
+    /// a sandboxed process.
+    ///
+    /// The descriptor is rewound before each read and is closed when the context is dropped.
+    ///
+    /// # Safety
+    ///
+    /// `fd` must be an open file descriptor that is owned by the caller and not used or closed
+    /// elsewhere, ownership passes to the context.
+    ///
+    #[cfg(unix)]
+    pub unsafe fn from_raw_fd(fd: std::os::unix::io::RawFd) -> io::Result<Self> {
+        use std::os::unix::io::FromRawFd;
+        CPUStatsContext::from_source(OpenStatsFile::new(std::fs::File::from_raw_fd(fd)))
+    }
+
+    ///
+    /// Context that only tracks the aggregate of all cores.
+    ///
+    /// Reading stops after the aggregate line (the first in the stats file), avoiding
+    /// parsing every core on large machines.
+    ///
+    pub fn new_aggregate_only() -> io::Result<Self> {
+        CPUStatsContext::aggregate_only_from_source(StatsFile::default())
+    }
+
+    ///
+    /// Context that only tracks the aggregate, reading from the supplied source.
+    ///
+    pub fn aggregate_only_from_source<S: StatsSource + 'static>(source: S) -> io::Result<Self> {
+        CPUStatsContext::with_options(
+            Arc::new(Mutex::new(source)),
+            ReadOptions { aggregate_only: true, ..ReadOptions::default() },
+        )
+    }
+
+    ///
+    /// Context that never tracks the aggregate of all cores.
+    ///
+    /// The aggregate is dropped when parsed so it is absent from `last_stats` and every
+    /// snapshot, saving filtering when only individual cores are of interest.
+    ///
+    pub fn new_per_core_only() -> io::Result<Self> {
+        CPUStatsContext::per_core_only_from_source(StatsFile::default())
+    }
+
+    ///
+    /// Context that never tracks the aggregate, reading from the supplied source.
+    ///
+    pub fn per_core_only_from_source<S: StatsSource + 'static>(source: S) -> io::Result<Self> {
+        CPUStatsContext::with_options(
+            Arc::new(Mutex::new(source)),
+            ReadOptions { per_core_only: true, ..ReadOptions::default() },
+        )
+    }
+
+    ///
+    /// Context tracking only the cores the calling thread is allowed to run on.
+    ///
+    /// The aggregate of all cores is still included.
+    ///
+    #[cfg(target_os = "linux")]
+    pub fn for_affinity() -> io::Result<Self> {
+        let mut context = CPUStatsContext::new()?;
+        context.restrict_to_cores(affinity::thread_affinity()?);
+        Ok(context)
+    }
+
+    fn with_options(source: Arc<Mutex<dyn StatsSource>>, options: ReadOptions) -> io::Result<Self> {
+        let mut context = Self {
+            last_stats: Vec::new(),
+            last_system: SystemStats::default(),
+            previous_system: SystemStats::default(),
+            last_instant: SystemClock.now(),
+            last_period: Duration::from_millis(0),
+            last_read_cost: Duration::from_millis(0),
+            clock: Box::new(SystemClock),
+            source,
+            timeout: None,
+            allowed_cores: None,
+            last_usage: None,
+            options,
+            sysfs_root: PathBuf::from(SYSFS_ROOT),
+            include_aggregate: true,
+            peak_usage: HashMap::new(),
+            core_index: HashMap::new(),
+            baseline: None,
+            steal_seen: false,
+            adaptive: None,
+            has_read: false,
+        };
+        let (stats, system) = context.raw_read()?;
+        context.last_stats = stats;
+        context.last_system = system;
+        context.reindex();
+        Ok(context)
+    }
+
+    ///
+    /// Enable strict parsing on subsequent reads.
+    ///
+    /// By default malformed core lines are skipped. In strict mode a malformed line, or core
+    /// lines with differing numbers of fields within a single read, fail the read with an
+    /// `InvalidData` error wrapping `CpuStatsError::Parse`.
+    ///
+    pub fn set_strict(&mut self, strict: bool) {
+        self.options.strict = strict;
+    }
+
+    ///
+    /// Only track the cores with the given indexes, the aggregate is always tracked.
+    ///
+    pub fn restrict_to_cores(&mut self, cores: BTreeSet<usize>) {
+        retain_allowed(&mut self.last_stats, &cores);
+        self.allowed_cores = Some(cores);
+        self.reindex();
+    }
+
+    ///
+    /// Limit how long a read waits for the stats source.
+    ///
+    /// With a timeout the source is read on a separate thread and a read that takes too long
+    /// fails with a `TimedOut` error wrapping `CpuStatsError::Timeout`. While a timed out read
+    /// is still in flight later reads fail immediately with the same error rather than
+    /// starting another thread to wait on the source, so a source that never responds holds
+    /// on to a single thread.
+    ///
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
+    ///
+    /// Replace the clock used to measure periods.
+    ///
+    /// The time of the last read is reset to the new clock's current instant so the next
+    /// period is measured entirely by the new clock.
+    ///
+    pub fn set_clock<C: Clock + 'static>(&mut self, clock: C) {
+        self.last_instant = clock.now();
+        self.clock = Box::new(clock);
+    }
+
+    ///
+    /// Parse the count of each individual IRQ on subsequent reads.
+    ///
+    /// Disabled by default as machines can have thousands of IRQs. See `per_irq_rates`.
+    ///
+    pub fn set_track_per_irq(&mut self, track: bool) {
+        self.options.per_irq = track;
+    }
+
+    ///
+    /// Track each core's usage between reads so snapshots can report `usage_change`.
+    ///
+    pub fn set_track_usage_change(&mut self, track: bool) {
+        self.last_usage = if track { Some(HashMap::new()) } else { None };
+    }
+
+    ///
+    /// Include the aggregate of all cores in the snapshots from `read`, on by default.
+    ///
+    /// The aggregate is still tracked so `read_snapshot` continues to include it.
+    ///
+    pub fn set_include_aggregate(&mut self, include: bool) {
+        self.include_aggregate = include;
+    }
+
+    ///
+    /// Read core frequencies from sysfs mounted at `root` rather than `/sys`.
+    ///
+    pub fn set_sysfs_root<P: Into<PathBuf>>(&mut self, root: P) {
+        self.sysfs_root = root.into();
+    }
+
+    ///
+    /// Map of core name to NUMA node, read from sysfs. See `Snapshot::by_numa_node`.
+    ///
+    pub fn numa_nodes(&self) -> io::Result<HashMap<String, u32>> {
+        read_numa_nodes(&self.sysfs_root)
+    }
+
+    ///
+    /// Names of the cores seen on the last read, optionally including the aggregate.
+    ///
+    pub fn core_names(&self, include_aggregate: bool) -> Vec<&str> {
+        self.last_stats.iter()
+            .filter(|core| include_aggregate || !core.is_aggregate())
+            .map(|core| core.name.as_str())
+            .collect()
+    }
+
+    ///
+    /// Highest `usage_percent` of a core over all reads by this context.
+    ///
+    /// `None` if the core has not been seen by a read yet.
+    ///
+    pub fn peak_usage(&self, name: &str) -> Option<f64> {
+        self.peak_usage.get(name).copied()
+    }
+
+    ///
+    /// Absolute counters of the aggregate from the last read, for lifetime usage without a diff.
+    ///
+    /// `None` for a per-core only context.
+    ///
+    pub fn last_aggregate(&self) -> Option<&CoreStats> {
+        self.last_stats.iter().find(|core| core.is_aggregate())
+    }
+
+    ///
+    /// Names of cores in `next` where any counter is lower than in the last read.
+    ///
+    /// For validating captured or replayed data, cores not in the last read are ignored. Note
+    /// I/O wait can legitimately decrease so is not a reliable sign of bad data on its own.
+    ///
+    pub fn check_monotonic(&self, next: &[CoreStats]) -> Vec<String> {
+        next.iter()
+            .filter(|core| {
+                self.position(&core.name).is_some_and(|index| {
+                    self.last_stats[index].fields()
+                        .zip(core.fields())
+                        .any(|((_, before), (_, after))| after < before)
+                })
+            })
+            .map(|core| core.name.clone())
+            .collect()
+    }
+
+    ///
+    /// Has any core ever reported steal time, a cheap hint that this is a virtual machine.
+    ///
+    /// A heuristic only: hypervisors need not report steal time and an idle host may not have
+    /// had any stolen yet.
+    ///
+    pub fn likely_virtualized(&self) -> bool {
+        self.steal_seen || self.last_stats.iter().any(|core| core.steal_time > 0)
+    }
+
+    ///
+    /// Period covered by the last read, zero before the first read.
+    ///
+    pub fn last_period(&self) -> Duration {
+        self.last_period
+    }
+
+    ///
+    /// Real time taken by the last read to read, parse and diff the stats.
+    ///
+    /// Useful to confirm sampling is cheap on machines with many cores. Zero before the
+    /// first read.
+    ///
+    pub fn last_read_cost(&self) -> Duration {
+        self.last_read_cost
+    }
+
+    ///
+    /// System wide stats from the last read.
+    ///
+    /// Not populated by aggregate-only contexts as the system lines follow the cores.
+    ///
+    pub fn system_stats(&self) -> &SystemStats {
+        &self.last_system
+    }
+
+    ///
+    /// Per second rate of each IRQ over the last period.
+    ///
+    /// Empty unless per-IRQ tracking was enabled before the previous two reads.
+    ///
+    pub fn per_irq_rates(&self) -> Vec<f64> {
+        self.last_system.per_irq_rates(&self.previous_system, self.last_period.as_secs_f64())
+    }
+
+    ///
+    /// Processes created per second over the last period, from the `processes` line.
+    ///
+    /// Zero before the second read or for aggregate-only contexts.
+    ///
+    pub fn fork_rate(&self) -> f64 {
+        self.last_system.fork_rate(&self.previous_system, self.last_period.as_secs_f64())
+    }
+
+    ///
+    /// Rough 0-1 score of how much time is likely lost to context switching.
+    ///
+    /// A heuristic: the `ctxt` rate over the last period is divided over every online core,
+    /// including any not tracked by the context, and scaled so that 10,000 switches per core
+    /// per second, around where switching overhead becomes noticeable on typical hardware, or
+    /// more scores 1. A few hundred per core per second is normal for an idle system. Zero
+    /// before the second read or for aggregate-only contexts.
+    ///
+    pub fn context_switch_pressure(&self) -> f64 {
+        let cores = self.last_system.online_cores().max(1);
+        let rate = self.last_system.context_switch_rate(&self.previous_system, self.last_period.as_secs_f64());
+        (rate / cores as f64 / HIGH_SWITCH_RATE).clamp(0.0, 1.0)
+    }
+
+    ///
+    /// Read stats and generate performance snapshot.
+    ///
+    /// Each snapshot covers the period since the previous read. The aggregate is omitted if
+    /// disabled with `set_include_aggregate`.
+    ///
+    pub fn read(&mut self) -> io::Result<Vec<CoreSnapshot>> {
+        let snapshot = self.read_snapshot()?;
+        Ok(self.output(snapshot))
+    }
+
+    ///
+    /// Cores to return from `read`
+    ///
+    fn output(&self, snapshot: Snapshot) -> Vec<CoreSnapshot> {
+        let mut cores = snapshot.into_cores();
+        if !self.include_aggregate {
+            cores.retain(|core| !core.is_aggregate());
+        }
+        cores
+    }
+
+    ///
+    /// Read stats and generate a snapshot including the cores that came online or went
+    /// offline since the previous read.
+    ///
+    /// Cores are matched to the previous read by name. Cores that have just come online
+    /// have no previous counters so snapshots are only produced for them from the next read.
+    ///
+    pub fn read_snapshot(&mut self) -> io::Result<Snapshot> {
+        let started = Instant::now();
+        let now = self.clock.now();
+        // Instant is monotonic so the period can be zero but never negative
+        let period = now.saturating_duration_since(self.last_instant);
+        let (now_stats, now_system) = self.raw_read()?;
+
+        let snapshot = self.advance(now_stats, now_system, period);
+        self.last_instant = now;
+        self.last_read_cost = started.elapsed();
+        Ok(snapshot)
+    }
+
+    ///
+    /// Generate snapshots from the contents of a stats file fetched by the caller, eg over SSH
+    /// from a remote host, covering the given period.
+    ///
+    /// The contents are diffed against the previous read or feed as with `read`, the
+    /// instant of the last read is not changed.
+    ///
+    pub fn feed(&mut self, contents: &str, period: Duration) -> io::Result<Vec<CoreSnapshot>> {
+        let (now_stats, now_system) = self.parse_contents(contents)?;
+        let snapshot = self.advance(now_stats, now_system, period);
+        Ok(self.output(snapshot))
+    }
+
+    ///
+    /// Diff newly read stats against the previous read and make them the new baseline
+    ///
+    fn advance(&mut self, now_stats: Vec<CoreStats>, now_system: SystemStats, period: Duration) -> Snapshot {
+        // Names are interned in the baseline, matched cores keep their existing entry and only
+        // the freshly parsed name moves into the delta, so each read allocates one name per core
+        // for the delta returned to the caller. See benches/allocations.rs.
+        let matches: Vec<Option<usize>> = now_stats.iter().map(|core| self.position(&core.name)).collect();
+        self.steal_seen = self.likely_virtualized();
+        let mut previous: Vec<Option<CoreStats>> = std::mem::take(&mut self.last_stats)
+            .into_iter()
+            .map(Some)
+            .collect();
+        let mut baseline = Vec::with_capacity(now_stats.len());
+        let mut snapshots = Vec::with_capacity(now_stats.len());
+        let mut newly_online = Vec::new();
+        for (core, index) in now_stats.into_iter().zip(matches) {
+            match index.and_then(|index| previous[index].take()) {
+                Some(mut last) => {
+                    #[cfg(feature = "log")]
+                    log_counter_anomalies(&last, &core);
+                    snapshots.push(CoreSnapshot::new(last.advance(core), period));
+                    baseline.push(last);
+                }
+                None => {
+                    newly_online.push(core.name.clone());
+                    baseline.push(core);
+                }
+            }
+        }
+        let went_offline = previous.into_iter()
+            .flatten()
+            .map(|core| core.name)
+            .collect::<Vec<String>>();
+
+        #[cfg(feature = "log")]
+        {
+            if !went_offline.is_empty() {
+                log::warn!("cores missing from stats: {}", went_offline.join(", "));
+            }
+            if self.last_period > Duration::from_millis(0) && period > self.last_period * 2 {
+                log::warn!("missed samples, period of {:?} is over twice the previous {:?}", period, self.last_period);
+            }
+        }
+
+        for core in &snapshots {
+            let usage = core.usage_percent();
+            match self.peak_usage.get_mut(&core.stats.name) {
+                Some(peak) => *peak = peak.max(usage),
+                None => { self.peak_usage.insert(core.stats.name.clone(), usage); }
+            }
+        }
+        if let Some(last_usage) = &mut self.last_usage {
+            for core in snapshots.iter_mut() {
+                core.previous_usage = last_usage.insert(core.stats.name.clone(), core.usage_percent());
+            }
+        }
+
+        self.last_period = period;
+        self.last_stats = baseline;
+        self.reindex();
+        self.previous_system = std::mem::replace(&mut self.last_system, now_system);
+
+        let first_read = !std::mem::replace(&mut self.has_read, true);
+        Snapshot::with_changes(snapshots, newly_online, went_offline, first_read)
+    }
+
+    ///
+    /// Read stats and the current frequency of each core as `(name, kHz, usage)`.
+    ///
+    /// A frequency drop while usage stays high hints at thermal throttling. The aggregate is
+    /// not included and the frequency is 0 for cores without cpufreq support.
+    ///
+    pub fn frequencies_and_usage(&mut self) -> io::Result<Vec<(String, u64, f64)>> {
+        let snapshots = self.read()?;
+        let sysfs_root: &Path = &self.sysfs_root;
+        snapshots.into_iter()
+            .filter(|core| !core.is_aggregate())
+            .map(|core| {
+                let khz = cpufreq::current_khz(sysfs_root, &core.stats.name)?.unwrap_or(0);
+                let usage = core.usage_percent();
+                Ok((core.stats.name, khz, usage))
+            })
+            .collect()
+    }
+
+    ///
+    /// Read the absolute counters since boot without diffing.
+    ///
+    /// The context's state is not changed so this does not affect the next `read`. Use
+    /// `CoreStats::delta_since` to diff two raw snapshots.
+    ///
+    pub fn raw_snapshot(&self) -> io::Result<Vec<CoreStats>> {
+        self.raw_read().map(|(cores, _)| cores)
+    }
+
+    ///
+    /// Refresh the baseline, sleep for the given duration and then read.
+    ///
+    /// Both the baseline and the final read record their instant immediately before the
+    /// stats file is read, so the reported period is the requested sleep plus the time taken
+    /// to read the baseline. Time spent since the previous read, and processing of that
+    /// read, is not included.
+    ///
+    pub fn read_after(&mut self, sleep: Duration) -> io::Result<Vec<CoreSnapshot>> {
+        let now = self.clock.now();
+        let (stats, system) = self.raw_read()?;
+        self.steal_seen = self.likely_virtualized();
+        self.last_stats = stats;
+        self.last_system = system;
+        self.last_instant = now;
+        self.reindex();
+
+        std::thread::sleep(sleep);
+        self.read()
+    }
+
+    ///
+    /// Sample until system wide usage settles, returning the final usage (0-100).
+    ///
+    /// Samples are taken every tenth of `max` until the aggregate usage changes by less than
+    /// `tolerance` percentage points between consecutive samples, or the sampled periods add up
+    /// to `max`, in which case the latest usage is returned. Fails with `InvalidData` if the
+    /// stats have no aggregate and individual cores to calculate system usage from.
+    ///
+    pub fn sample_until_stable(&mut self, tolerance: f64, max: Duration) -> io::Result<f64> {
+        let interval = max / 10;
+        let mut elapsed = Duration::from_millis(0);
+        let mut previous: Option<f64> = None;
+        loop {
+            thread::sleep(interval);
+            let snapshot = self.read_snapshot()?;
+            let usage = snapshot.aggregate_usage().ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidData, "no aggregate to calculate usage from")
+            })?;
+            elapsed += self.last_period;
+
+            let stable = previous.is_some_and(|previous| (usage - previous).abs() < tolerance);
+            if stable || elapsed >= max {
+                return Ok(usage);
+            }
+            previous = Some(usage);
+        }
+    }
+
+    ///
+    /// Save the counters from the most recent read to a JSON file.
+    ///
+    /// Load it later with `load_baseline` to diff against, eg comparing before and after a
+    /// deploy across runs.
+    ///
+    #[cfg(feature = "serde")]
+    pub fn save_baseline<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        use std::io::Write;
+
+        let mut writer = io::BufWriter::new(std::fs::File::create(path)?);
+        serde_json::to_writer(&mut writer, &self.last_stats).map_err(io::Error::from)?;
+        writer.flush()
+    }
+
+    ///
+    /// Replace the counters from the previous read with those saved by `save_baseline`.
+    ///
+    /// The next `read` is diffed against the loaded counters. Its period is still measured
+    /// from the previous read as the saved counters carry no time.
+    ///
+    #[cfg(feature = "serde")]
+    pub fn load_baseline<P: AsRef<Path>>(&mut self, path: P) -> io::Result<()> {
+        let file = std::fs::File::open(path)?;
+        let mut stats: Vec<CoreStats> = serde_json::from_reader(io::BufReader::new(file)).map_err(io::Error::from)?;
+        if let Some(allowed) = &self.allowed_cores {
+            retain_allowed(&mut stats, allowed);
+        }
+        self.last_stats = stats;
+        self.reindex();
+        Ok(())
+    }
+
+    ///
+    /// Mark the counters from the most recent read as the baseline for `read_since_baseline`.
+    ///
+    pub fn mark_baseline(&mut self) {
+        self.baseline = Some((self.last_stats.clone(), self.last_instant));
+    }
+
+    ///
+    /// Read stats and generate snapshots covering the period since the marked baseline.
+    ///
+    /// Useful to measure the CPU cost of a bounded operation. Cores not in the baseline are
+    /// skipped and the rolling state used by `read` is not changed. Fails with `InvalidInput`
+    /// if no baseline has been marked.
+    ///
+    pub fn read_since_baseline(&mut self) -> io::Result<Vec<CoreSnapshot>> {
+        let (baseline, since) = self.baseline.as_ref().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "no baseline marked")
+        })?;
+        let period = self.clock.now().saturating_duration_since(*since);
+        let (now_stats, _) = self.raw_read()?;
+
+        Ok(now_stats.iter()
+            .filter_map(|core| {
+                let earlier = baseline.iter().find(|earlier| earlier.name == core.name)?;
+                Some(CoreSnapshot::new(core.delta_since(earlier), period))
+            })
+            .collect())
+    }
+
+    ///
+    /// Sleep for an interval adapted to recent activity and then read.
+    ///
+    /// The interval starts at `min`. After each sample it doubles if system usage changed by
+    /// less than 5 percentage points since the previous sample and halves if it changed by
+    /// more than 20, always staying within `min` and `max`. This saves sampling overhead while
+    /// the system is quiet. Usage is only compared when the stats include the aggregate.
+    ///
+    /// Fails with `InvalidInput` if `min` is zero, as the interval could never grow, or greater
+    /// than `max`.
+    ///
+    pub fn adaptive_sample(&mut self, min: Duration, max: Duration) -> io::Result<Vec<CoreSnapshot>> {
+        if min.is_zero() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "minimum interval is zero"));
+        }
+        if min > max {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "minimum interval is greater than the maximum"));
+        }
+        let (interval, previous) = self.adaptive.unwrap_or((min, None));
+        let interval = interval.clamp(min, max);
+        thread::sleep(interval);
+
+        let snapshot = self.read_snapshot()?;
+        let usage = snapshot.aggregate_usage();
+        let next = match (previous, usage) {
+            (Some(previous), Some(usage)) if (usage - previous).abs() < STABLE_USAGE_CHANGE => interval * 2,
+            (Some(previous), Some(usage)) if (usage - previous).abs() > VOLATILE_USAGE_CHANGE => interval / 2,
+            _ => interval,
+        };
+        self.adaptive = Some((next.clamp(min, max), usage));
+        Ok(self.output(snapshot))
+    }
+
+    ///
+    /// Interval `adaptive_sample` will sleep for next, `None` before the first adaptive sample.
+    ///
+    pub fn adaptive_interval(&self) -> Option<Duration> {
+        self.adaptive.map(|(interval, _)| interval)
+    }
+
+    ///
+    /// Move the context to a background thread that reads every `interval`.
+    ///
+    /// Snapshots are sent over the returned channel. The thread stops once the receiver is
+    /// dropped (after its next read) or if a read fails, the error is returned on join.
+    ///
+    pub fn spawn_sampler(mut self, interval: Duration) -> (JoinHandle<io::Result<()>>, mpsc::Receiver<Vec<CoreSnapshot>>) {
+        let (sender, receiver) = mpsc::channel();
+        let handle = thread::spawn(move || {
+            loop {
+                thread::sleep(interval);
+                if sender.send(self.read()?).is_err() {
+                    return Ok(());
+                }
+            }
+        });
+        (handle, receiver)
+    }
+
+    ///
+    /// Position of a core in `last_stats`.
+    ///
+    /// Falls back to a search on a miss or stale entry as `last_stats` can be changed directly,
+    /// leaving the index out of date.
+    ///
+    fn position(&self, name: &str) -> Option<usize> {
+        match self.core_index.get(name) {
+            Some(&index) if self.last_stats.get(index).is_some_and(|core| core.name == name) => Some(index),
+            _ => self.last_stats.iter().position(|core| core.name == name),
+        }
+    }
+
+    ///
+    /// Rebuild the name index if `last_stats` has changed shape, eg cores were reordered.
+    ///
+    fn reindex(&mut self) {
+        let current = self.core_index.len() == self.last_stats.len()
+            && self.last_stats.iter().enumerate().all(|(index, core)| self.core_index.get(&core.name) == Some(&index));
+        if !current {
+            self.core_index = self.last_stats.iter()
+                .enumerate()
+                .map(|(index, core)| (core.name.clone(), index))
+                .collect();
+        }
+    }
+
+    ///
+    /// Read raw core and system stats
+    ///
+    fn raw_read(&self) -> io::Result<(Vec<CoreStats>, SystemStats)> {
+        let contents = match self.timeout {
+            Some(timeout) => read_with_timeout(&self.source, timeout)?,
+            None => self.source.lock().unwrap_or_else(PoisonError::into_inner).read_stats()?,
+        };
+        self.parse_contents(&contents)
+    }
+
+    ///
+    /// Parse core and system stats from the contents of a stats file
+    ///
+    fn parse_contents(&self, contents: &str) -> io::Result<(Vec<CoreStats>, SystemStats)> {
+        // The core lines seldom change between reads so size for those in the previous read
+        let capacity = self.last_system.online_cores() + 1;
+        let mut cores = parse_cores_with_capacity(contents, self.options, capacity)?;
+        if let Some(allowed) = &self.allowed_cores {
+            retain_allowed(&mut cores, allowed);
+        }
+        let system = if self.options.aggregate_only {
+            SystemStats::default()
+        } else {
+            SystemStats::parse(contents, self.options.per_irq)
+        };
+        Ok((cores, system))
+    }
+}
+
+///
+/// Warn about counters that went backwards between reads
+///
+#[cfg(feature = "log")]
+fn log_counter_anomalies(last: &CoreStats, now: &CoreStats) {
+    for ((field, before), (_, after)) in last.fields().zip(now.fields()) {
+        if after >= before { continue }
+        if field == "iowait" {
+            log::warn!("{} iowait decreased from {} to {}", now.name, before, after);
+        } else {
+            log::warn!("{} {} counter reset from {} to {}", now.name, field, before, after);
+        }
+    }
+}
+
+///
+/// Remove cores not in the allowed set, keeping the aggregate
+///
+fn retain_allowed(cores: &mut Vec<CoreStats>, allowed: &BTreeSet<usize>) {
+    cores.retain(|core| core.index().is_none_or(|index| allowed.contains(&index)));
+}
+
+///
+/// Read the source on a separate thread, giving up after the timeout.
+///
+/// Fails immediately if an earlier read that timed out still holds the source.
+///
+fn read_with_timeout(source: &Arc<Mutex<dyn StatsSource>>, timeout: Duration) -> io::Result<String> {
+    let source = Arc::clone(source);
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let result = match source.try_lock() {
+            Ok(mut source) => source.read_stats(),
+            Err(TryLockError::Poisoned(poisoned)) => poisoned.into_inner().read_stats(),
+            Err(TryLockError::WouldBlock) => Err(CpuStatsError::Timeout.into()),
+        };
+        let _ = sender.send(result);
+    });
+
+    match receiver.recv_timeout(timeout) {
+        Ok(result) => result,
+        Err(mpsc::RecvTimeoutError::Timeout) => Err(CpuStatsError::Timeout.into()),
+        Err(mpsc::RecvTimeoutError::Disconnected) => {
+            Err(io::Error::other("stats source failed"))
+        }
+    }
+}
+
+///
+/// Parse the core stats, including the aggregate, from the contents of a stats file.
+///
+/// Malformed core lines are skipped.
+///
+pub fn parse_stats(contents: &str) -> io::Result<Vec<CoreStats>> {
+    parse_cores(contents, ReadOptions::default())
+}
+
+///
+/// Read the absolute core stats since boot, including the aggregate, from `/proc/stat`.
+///
+/// For one-off reads without a `CPUStatsContext`.
+///
+pub fn read_raw() -> io::Result<Vec<CoreStats>> {
+    read_raw_from(STATS_FILE)
+}
+
+///
+/// Read the absolute core stats from a file in `/proc/stat` format.
+///
+pub fn read_raw_from<P: AsRef<Path>>(path: P) -> io::Result<Vec<CoreStats>> {
+    parse_stats(&std::fs::read_to_string(path)?)
+}
+
+fn parse_cores(contents: &str, options: ReadOptions) -> io::Result<Vec<CoreStats>> {
+    parse_cores_with_capacity(contents, options, 0)
+}
+
+///
+/// Parse the core lines into a vec pre-sized for the expected number of cores
+///
+fn parse_cores_with_capacity(contents: &str, options: ReadOptions, capacity: usize) -> io::Result<Vec<CoreStats>> {
+    let mut cores: Vec<CoreStats> = Vec::with_capacity(capacity);
+    let mut field_count: Option<usize> = None;
+    for line in contents.lines() {
+        if !line.starts_with("cpu") { continue }
+
+        if options.strict {
+            let count = line.split_whitespace().count() - 1;
+            if *field_count.get_or_insert(count) != count {
+                return Err(CpuStatsError::Parse(format!(
+                    "expected {} fields, found {} in `{}`", field_count.unwrap(), count, line
+                )).into());
+            }
+        }
+
+        match line.parse::<CoreStats>() {
+            Ok(core) => {
+                let is_aggregate = core.is_aggregate();
+                if !(options.per_core_only && is_aggregate) {
+                    cores.push(core);
+                }
+                if options.aggregate_only && is_aggregate { break }
+            }
+            Err(err) if options.strict => return Err(err.into()),
+            Err(_) => {}
+        }
+    }
+    Ok(cores)
+}
+
+///
+/// Snapshot of a stats line covering the period, for tests
+///
+#[cfg(test)]
+pub(crate) fn core_snapshot(line: &str, period_ms: u64) -> CoreSnapshot {
+    CoreSnapshot::new(CoreStats::from_str(line).unwrap(), Duration::from_millis(period_ms))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn refresh_stats() {
+        let mut stats_context = super::CPUStatsContext::new().unwrap();
+
+        assert!(stats_context.read().is_ok())
+    }
+
+    const PROC_STAT: &str = "\
+cpu  400 20 300 8000 40 10 5 0 0 0
+cpu0 100 5 75 2000 10 3 1 0 0 0
+cpu1 100 5 75 2000 10 2 1 0 0 0
+cpu2 100 5 75 2000 10 3 2 0 0 0
+cpu3 100 5 75 2000 10 2 1 0 0 0
+intr 12345 0 0
+ctxt 67890
+btime 1600000000
+processes 4321
+procs_running 2
+procs_blocked 0
+";
+
+    #[test]
+    fn try_from_str() {
+        let stats = CoreStats::try_from("cpu2 1 2 3 4 5 6 7 8 9 10").unwrap();
+        assert_eq!(stats.name, "cpu2");
+        assert_eq!(stats.user_processes, 1);
+        assert_eq!(stats.guest_nice, 10);
+
+        let parsed: CoreStats = "cpu2 1 2 3 4 5 6 7 8 9 10".parse().unwrap();
+        assert_eq!(parsed.idle_time, 4);
+
+        match CoreStats::try_from("cpu2 1 2 x 4 5 6 7 8 9 10") {
+            Err(CpuStatsError::Parse(msg)) => assert_eq!(msg, "invalid system field `x`"),
+            _ => panic!("expected parse error"),
+        }
+        assert!(CoreStats::try_from("cpu2 1 2 3").is_err());
+        assert!(CoreStats::try_from("intr 1 2 3 4 5 6 7 8 9 10").is_err());
+    }
+
+    #[test]
+    fn aggregate_two_space_line() {
+        let stats: CoreStats = "cpu  10132153 290696 3084719 46828483 16683 0 25195 0 175628 0".parse().unwrap();
+
+        assert_eq!(stats.name, "cpu");
+        assert!(stats.is_aggregate());
+        assert_eq!(stats.fields().map(|(_, value)| value).collect::<Vec<u64>>(), vec![
+            10132153, 290696, 3084719, 46828483, 16683, 0, 25195, 0, 175628, 0,
+        ]);
+    }
+
+    #[test]
+    fn merge() {
+        let stats = vec![
+            CoreStats::from_str("cpu0 1 2 3 4 5 6 7 8 9 10").unwrap(),
+            CoreStats::from_str("cpu0 10 20 30 40 50 60 70 80 90 100").unwrap(),
+            CoreStats::from_str("cpu0 100 200 300 400 500 600 700 800 900 1000").unwrap(),
+        ];
+
+        assert_eq!(
+            CoreStats::merge(&stats),
+            Some(CoreStats::from_str("cpu0 111 222 333 444 555 666 777 888 999 1110").unwrap())
+        );
+        assert_eq!(CoreStats::merge(&[]), None);
+
+        let mixed = vec![stats[0].clone(), CoreStats::from_str("cpu1 1 2 3 4 5 6 7 8 9 10").unwrap()];
+        assert_eq!(CoreStats::merge(&mixed), None);
+    }
+
+    #[test]
+    fn idle_duration() {
+        let snapshot = core_snapshot("cpu0 30 0 20 50 0 0 0 0 0 0", 1000);
+
+        assert_eq!(snapshot.stats.idle_duration(USER_HZ), Duration::from_millis(500));
+        assert_eq!(snapshot.stats.idle_duration(250), Duration::from_millis(200));
+        assert_eq!(snapshot.stats.idle_duration(0), Duration::from_secs(0));
+    }
+
+    #[test]
+    fn fields() {
+        let stats = CoreStats::from_str("cpu0 1 2 3 4 5 6 7 8 9 10").unwrap();
+        let fields: Vec<(&str, u64)> = stats.fields().collect();
+
+        assert_eq!(fields, vec![
+            ("user", 1),
+            ("nice", 2),
+            ("system", 3),
+            ("idle", 4),
+            ("iowait", 5),
+            ("irq", 6),
+            ("softirq", 7),
+            ("steal", 8),
+            ("guest", 9),
+            ("guest_nice", 10),
+        ]);
+    }
+
+    fn context(contents: &[&str]) -> CPUStatsContext {
+        CPUStatsContext::from_source(source::Sequence::new(contents)).unwrap()
+    }
+
+    ///
+    /// Source that responds immediately to the first read and slowly after that
+    ///
+    struct Slow {
+        delay: Duration,
+        reads: usize,
+    }
+
+    impl StatsSource for Slow {
+        fn read_stats(&mut self) -> io::Result<String> {
+            if self.reads > 0 {
+                std::thread::sleep(self.delay);
+            }
+            self.reads += 1;
+            Ok(PROC_STAT.to_string())
+        }
+    }
+
+    #[test]
+    fn read_timeout() {
+        let source = Slow { delay: Duration::from_millis(500), reads: 0 };
+        let mut stats_context = CPUStatsContext::from_source(source).unwrap();
+        stats_context.set_timeout(Some(Duration::from_millis(20)));
+
+        let started = Instant::now();
+        let err = stats_context.read().unwrap_err();
+        assert!(started.elapsed() < Duration::from_millis(400));
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        match err.into_inner().unwrap().downcast::<CpuStatsError>() {
+            Ok(err) => assert!(matches!(*err, CpuStatsError::Timeout)),
+            Err(_) => panic!("expected CpuStatsError"),
+        }
+    }
+
+    ///
+    /// Source that responds to the first read and never again
+    ///
+    struct Hung {
+        reads: usize,
+    }
+
+    impl StatsSource for Hung {
+        fn read_stats(&mut self) -> io::Result<String> {
+            self.reads += 1;
+            if self.reads > 1 {
+                loop { std::thread::park() }
+            }
+            Ok(PROC_STAT.to_string())
+        }
+    }
+
+    #[test]
+    fn hung_source_holds_one_thread() {
+        let mut stats_context = CPUStatsContext::from_source(Hung { reads: 0 }).unwrap();
+        stats_context.set_timeout(Some(Duration::from_millis(20)));
+
+        for _ in 0..5 {
+            let err = stats_context.read().unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        }
+
+        // Each reader thread holds a reference to the source until it finishes, only the
+        // first is still blocked in the source
+        let mut references = Arc::strong_count(&stats_context.source);
+        for _ in 0..100 {
+            if references <= 2 { break }
+            std::thread::sleep(Duration::from_millis(5));
+            references = Arc::strong_count(&stats_context.source);
+        }
+        assert_eq!(references, 2);
+    }
+
+    #[test]
+    fn last_period() {
+        let mut stats_context = context(&[PROC_STAT]);
+        stats_context.set_clock(stepping_clock(Duration::from_millis(20)));
+        assert_eq!(stats_context.last_period(), Duration::from_millis(0));
+
+        for _ in 0..2 {
+            let snapshots = stats_context.read().unwrap();
+            assert_eq!(stats_context.last_period(), Duration::from_millis(20));
+            for core in snapshots {
+                assert_eq!(core.period(), stats_context.last_period());
+            }
+        }
+    }
+
+    #[test]
+    fn raw_snapshot() {
+        let mut stats_context = context(&[
+            "cpu0 100 0 50 1000 0 0 0 0 0 0",
+            "cpu0 150 0 60 1100 0 0 0 0 0 0",
+            "cpu0 170 0 65 1200 0 0 0 0 0 0",
+        ]);
+
+        let raw = stats_context.raw_snapshot().unwrap();
+        assert_eq!(raw[0].user_processes, 150);
+        assert_eq!(raw[0].idle_time, 1100);
+
+        let delta = stats_context.raw_snapshot().unwrap()[0].delta_since(&raw[0]);
+        assert_eq!(delta.user_processes, 20);
+
+        let snapshots = stats_context.read().unwrap();
+        assert_eq!(snapshots[0].stats.user_processes, 70);
+    }
+
+    #[test]
+    fn last_read_cost() {
+        let mut stats_context = context(&[PROC_STAT]);
+        assert_eq!(stats_context.last_read_cost(), Duration::from_millis(0));
+
+        stats_context.read().unwrap();
+        assert!(stats_context.last_read_cost() > Duration::from_millis(0));
+    }
+
+    #[test]
+    fn read_after() {
+        let mut stats_context = context(&[
+            "cpu0 100 0 50 1000 0 0 0 0 0 0",
+            "cpu0 150 0 60 1100 0 0 0 0 0 0",
+            "cpu0 170 0 65 1200 0 0 0 0 0 0",
+        ]);
+        stats_context.set_clock(stepping_clock(Duration::from_millis(50)));
+
+        // Only the time since the refreshed baseline is counted, not since the clock was set
+        let snapshots = stats_context.read_after(Duration::from_millis(1)).unwrap();
+        assert_eq!(stats_context.last_period(), Duration::from_millis(50));
+        assert_eq!(snapshots[0].period(), Duration::from_millis(50));
+        assert_eq!(snapshots[0].stats.user_processes, 20);
+    }
+
+    #[test]
+    fn spawn_sampler() {
+        let stats_context = context(&[PROC_STAT]);
+        let (handle, receiver) = stats_context.spawn_sampler(Duration::from_millis(5));
+
+        for _ in 0..2 {
+            let snapshots = receiver.recv().unwrap();
+            assert_eq!(snapshots.len(), 5);
+        }
+        drop(receiver);
+
+        assert!(handle.join().unwrap().is_ok());
+    }
+
+    ///
+    /// Backdate the last read so the next read covers roughly one second
+    ///
+    fn backdate(stats_context: &mut CPUStatsContext) {
+        stats_context.last_instant = Instant::now() - Duration::from_millis(1000);
+    }
+
+    #[derive(Clone)]
+    struct FakeClock(Arc<Mutex<Instant>>);
+
+    impl FakeClock {
+        fn advance(&self, by: Duration) {
+            *self.0.lock().unwrap() += by;
+        }
+    }
+
+    impl Clock for FakeClock {
+        fn now(&self) -> Instant {
+            *self.0.lock().unwrap()
+        }
+    }
+
+    ///
+    /// Clock that moves forward a fixed step each time it is read
+    ///
+    struct SteppingClock {
+        now: Mutex<Instant>,
+        step: Duration,
+    }
+
+    impl Clock for SteppingClock {
+        fn now(&self) -> Instant {
+            let mut now = self.now.lock().unwrap();
+            *now += self.step;
+            *now
+        }
+    }
+
+    fn stepping_clock(step: Duration) -> SteppingClock {
+        SteppingClock { now: Mutex::new(Instant::now()), step }
+    }
+
+    #[test]
+    fn sample_until_stable() {
+        let mut stats_context = context(&[
+            "cpu  0 0 0 0 0 0 0 0 0 0\ncpu0 0 0 0 0 0 0 0 0 0 0",
+            "cpu  8 0 0 2 0 0 0 0 0 0\ncpu0 8 0 0 2 0 0 0 0 0 0",
+            "cpu  11 0 0 9 0 0 0 0 0 0\ncpu0 11 0 0 9 0 0 0 0 0 0",
+            "cpu  16 0 0 14 0 0 0 0 0 0\ncpu0 16 0 0 14 0 0 0 0 0 0",
+            "cpu  21 0 0 19 0 0 0 0 0 0\ncpu0 21 0 0 19 0 0 0 0 0 0",
+        ]);
+        stats_context.set_clock(stepping_clock(Duration::from_millis(100)));
+
+        let usage = stats_context.sample_until_stable(1.0, Duration::from_secs(1)).unwrap();
+        assert_eq!(usage, 50.0);
+    }
+
+    #[test]
+    fn fake_clock_period() {
+        let clock = FakeClock(Arc::new(Mutex::new(Instant::now())));
+        let mut stats_context = context(&[PROC_STAT]);
+        stats_context.set_clock(clock.clone());
+
+        clock.advance(Duration::from_millis(500));
+        let snapshots = stats_context.read().unwrap();
+
+        assert_eq!(stats_context.last_period(), Duration::from_millis(500));
+        for core in snapshots {
+            assert_eq!(core.period(), Duration::from_millis(500));
+        }
+    }
+
+    #[test]
+    fn zero_period() {
+        let clock = FakeClock(Arc::new(Mutex::new(Instant::now())));
+        let mut stats_context = context(&[
+            "cpu  0 0 0 0 0 0 0 0 0 0\ncpu0 0 0 0 0 0 0 0 0 0 0\nintr 0 0",
+            "cpu  10 0 5 30 0 0 0 0 0 0\ncpu0 10 0 5 30 0 0 0 0 0 0\nintr 10 10",
+        ]);
+        stats_context.set_clock(clock);
+        stats_context.set_track_per_irq(true);
+
+        // System usage is a ratio of the aggregate's jiffies so does not depend on the period
+        for usage in &[Some(15.0 * 100.0 / 45.0), None] {
+            let snapshot = stats_context.read_snapshot().unwrap();
+            assert_eq!(stats_context.last_period(), Duration::from_millis(0));
+            assert_eq!(stats_context.per_irq_rates(), vec![0.0]);
+            assert_eq!(snapshot.aggregate_usage(), *usage);
+            assert!(!snapshot.is_system_idle(1.0));
+            for core in snapshot.cores() {
+                assert_eq!(core.period_ms(), 0);
+                assert_eq!(core.idle_percent(), 0);
+                assert_eq!(core.checked_idle_percent(), None);
+                assert_eq!(core.usage_percent(), 0.0);
+                assert_eq!(core.compute_percent(), 0.0);
+                assert_eq!(core.weighted_usage(0.5), 0.0);
+                assert_eq!(core.dominant_category().1, 0.0);
+                assert_eq!(core.to_string(), format!("{}:   0%", core.stats.name));
+            }
+        }
+    }
+
+    #[cfg(feature = "log")]
+    mod logging {
+        use std::sync::{Mutex, Once};
+        use log::{Log, Metadata, Record};
+        use super::*;
+
+        static MESSAGES: Mutex<Vec<String>> = Mutex::new(Vec::new());
+        static INIT: Once = Once::new();
+
+        struct Capture;
+
+        impl Log for Capture {
+            fn enabled(&self, _: &Metadata) -> bool { true }
+
+            fn log(&self, record: &Record) {
+                MESSAGES.lock().unwrap().push(format!("{} {}", record.level(), record.args()));
+            }
+
+            fn flush(&self) {}
+        }
+
+        #[test]
+        fn warns_on_counter_reset() {
+            INIT.call_once(|| {
+                log::set_logger(&Capture).unwrap();
+                log::set_max_level(log::LevelFilter::Warn);
+            });
+
+            let mut stats_context = context(&[
+                "cpu0 500 0 0 0 9 0 0 0 0 0\ncpu1 0 0 0 0 0 0 0 0 0 0",
+                "cpu0 20 0 0 0 4 0 0 0 0 0",
+            ]);
+            stats_context.read().unwrap();
+
+            let messages = MESSAGES.lock().unwrap();
+            assert!(messages.contains(&String::from("WARN cpu0 user counter reset from 500 to 20")), "{:?}", messages);
+            assert!(messages.contains(&String::from("WARN cpu0 iowait decreased from 9 to 4")), "{:?}", messages);
+            assert!(messages.contains(&String::from("WARN cores missing from stats: cpu1")), "{:?}", messages);
+        }
+    }
+
+    #[test]
+    fn per_irq_rates() {
+        let clock = FakeClock(Arc::new(Mutex::new(Instant::now())));
+        let mut stats_context = context(&[
+            "cpu0 0 0 0 0 0 0 0 0 0 0\nintr 30 10 20",
+            "cpu0 0 0 0 0 0 0 0 0 0 0\nintr 50 20 30",
+            "cpu0 0 0 0 0 0 0 0 0 0 0\nintr 130 60 70",
+        ]);
+        stats_context.set_clock(clock.clone());
+        stats_context.set_track_per_irq(true);
+
+        stats_context.read().unwrap();
+        assert_eq!(stats_context.system_stats().per_irq, vec![20, 30]);
+
+        clock.advance(Duration::from_secs(2));
+        stats_context.read().unwrap();
+        assert_eq!(stats_context.system_stats().interrupts, 130);
+        assert_eq!(stats_context.per_irq_rates(), vec![20.0, 20.0]);
+    }
+
+    #[test]
+    fn usage_change() {
+        let mut stats_context = context(&[
+            "cpu0 0 0 0 0 0 0 0 0 0 0",
+            "cpu0 20 0 0 80 0 0 0 0 0 0",
+            "cpu0 70 0 0 130 0 0 0 0 0 0",
+            "cpu0 80 0 0 220 0 0 0 0 0 0",
+        ]);
+        stats_context.set_track_usage_change(true);
+
+        backdate(&mut stats_context);
+        assert_eq!(stats_context.read().unwrap()[0].usage_change(), None);
+
+        backdate(&mut stats_context);
+        let change = stats_context.read().unwrap()[0].usage_change().unwrap();
+        assert!((change - 30.0).abs() < 0.5, "{}", change);
+
+        backdate(&mut stats_context);
+        let change = stats_context.read().unwrap()[0].usage_change().unwrap();
+        assert!((change + 40.0).abs() < 0.5, "{}", change);
+    }
+
+    #[test]
+    fn restrict_to_cores() {
+        let mut stats_context = context(&[PROC_STAT]);
+        stats_context.restrict_to_cores(vec![1, 3].into_iter().collect());
+        assert_eq!(stats_context.core_names(true), vec!["cpu", "cpu1", "cpu3"]);
+
+        let names: Vec<String> = stats_context.read().unwrap().into_iter()
+            .map(|core| core.stats.name)
+            .collect();
+        assert_eq!(names, vec!["cpu", "cpu1", "cpu3"]);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn for_affinity() {
+        let stats_context = CPUStatsContext::for_affinity().unwrap();
+        assert!(!stats_context.core_names(false).is_empty());
+    }
+
+    #[test]
+    fn hotplug() {
+        let mut stats_context = context(&[
+            "cpu0 10 0 0 0 0 0 0 0 0 0\ncpu1 10 0 0 0 0 0 0 0 0 0\ncpu2 10 0 0 0 0 0 0 0 0 0",
+            "cpu0 30 0 0 0 0 0 0 0 0 0\ncpu2 15 0 0 0 0 0 0 0 0 0\ncpu3 5 0 0 0 0 0 0 0 0 0",
+        ]);
+
+        let snapshot = stats_context.read_snapshot().unwrap();
+        assert_eq!(snapshot.newly_online(), ["cpu3"]);
+        assert_eq!(snapshot.went_offline(), ["cpu1"]);
+
+        let deltas: Vec<(&str, u64)> = snapshot.cores().iter()
+            .map(|core| (core.stats.name.as_str(), core.stats.user_processes))
+            .collect();
+        assert_eq!(deltas, vec![("cpu0", 20), ("cpu2", 5)]);
+    }
+
+    #[test]
+    fn core_names() {
+        let stats_context = context(&[PROC_STAT]);
+
+        assert_eq!(stats_context.core_names(true), vec!["cpu", "cpu0", "cpu1", "cpu2", "cpu3"]);
+        assert_eq!(stats_context.core_names(false), vec!["cpu0", "cpu1", "cpu2", "cpu3"]);
+    }
+
+    #[test]
+    fn aggregate_only() {
+        let options = ReadOptions { aggregate_only: true, ..ReadOptions::default() };
+        let cores = parse_cores(PROC_STAT, options).unwrap();
+        assert_eq!(cores.len(), 1);
+        assert!(cores[0].is_aggregate());
+
+        let stats_context = CPUStatsContext::new_aggregate_only().unwrap();
+        assert_eq!(stats_context.last_stats.len(), 1);
+        assert!(stats_context.last_stats[0].is_aggregate());
+    }
+
+    #[test]
+    fn strict_field_counts() {
+        let contents = "\
+cpu  200 10 150 4000 20 5 2 0 0 0
+cpu0 100 5 75 2000 10 3 1 0 0 0
+cpu1 100 5 75 2000 10 2 1 0 0 0 0
+";
+        let lenient = parse_cores(contents, ReadOptions::default()).unwrap();
+        assert_eq!(lenient.len(), 3);
+
+        let strict = ReadOptions { strict: true, ..ReadOptions::default() };
+        let err = parse_cores(contents, strict).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.into_inner().unwrap().is::<CpuStatsError>());
+
+        assert_eq!(parse_cores(PROC_STAT, strict).unwrap().len(), 5);
+    }
+
+    #[test]
+    fn snapshot_is_aggregate() {
+        let mut stats_context = context(&[PROC_STAT]);
+        let snapshots = stats_context.read().unwrap();
+
+        assert!(snapshots[0].is_aggregate());
+        for (core, stats) in snapshots.iter().zip(&stats_context.last_stats) {
+            assert_eq!(core.is_aggregate(), stats.is_aggregate());
+        }
+    }
+
+    #[test]
+    fn checked_idle_percent_sub_millisecond() {
+        let snapshot = CoreSnapshot::new(CoreStats::from_str("cpu0 0 0 0 1 0 0 0 0 0 0").unwrap(), Duration::from_micros(1500));
+        assert_eq!(snapshot.checked_idle_percent(), Some(666));
+
+        let snapshot = CoreSnapshot::new(CoreStats::from_str("cpu0 1 0 0 1 0 0 0 0 0 0").unwrap(), Duration::from_micros(500));
+        assert_eq!(snapshot.checked_idle_percent(), Some(2000));
+        assert_eq!(snapshot.usage_percent(), 2000.0);
+    }
+
+    #[test]
+    fn display_zero_period() {
+        let snapshot = core_snapshot("cpu0 1 2 3 50 5 6 7 8 9 10", 0);
+
+        assert_eq!(snapshot.checked_idle_percent(), None);
+        assert_eq!(snapshot.to_string(), "cpu0:   0%");
+    }
+
+    #[test]
+    fn display_alternate_period() {
+        let snapshot = core_snapshot("cpu0 30 0 6 64 0 0 0 0 0 0", 1002);
+
+        assert_eq!(format!("{}", snapshot), "cpu0:  63%");
+        assert_eq!(format!("{:#}", snapshot), "cpu0:  63% over 1002ms");
+        assert_eq!(format!("{:#}", core_snapshot("cpu1 0 0 0 0 0 0 0 0 0 0", 0)), "cpu1:   0% over 0ms");
+    }
+
+    #[test]
+    fn steal_independent_of_guest() {
+        let before = CoreStats::from_str("cpu0 100 10 50 1000 5 1 1 20 40 4").unwrap();
+        let after = CoreStats::from_str("cpu0 200 10 60 1500 5 1 1 27 90 4").unwrap();
+        let snapshot = CoreSnapshot::new(before.diff(&after), Duration::from_millis(1000));
+
+        assert_eq!(snapshot.steal_jiffies(), 7);
+        assert_eq!(snapshot.stats.guest, 50);
+    }
+
+    #[test]
+    fn weighted_usage() {
+        let snapshot = core_snapshot("cpu0 10 60 10 20 0 0 0 0 0 0", 1000);
+
+        assert_eq!(snapshot.weighted_usage(1.0), snapshot.usage_percent());
+        assert_eq!(snapshot.weighted_usage(0.5), 50.0);
+        assert_eq!(snapshot.weighted_usage(0.0), 20.0);
+        assert!(snapshot.weighted_usage(0.0) < snapshot.usage_percent());
+    }
+
+    #[test]
+    fn compute_percent() {
+        let snapshot = core_snapshot("cpu0 20 5 10 30 15 3 2 15 8 0", 1000);
+
+        assert_eq!(snapshot.compute_percent(), 40.0);
+        assert_eq!(snapshot.usage_percent(), 55.0);
+    }
+
+    #[test]
+    fn user_system_ratio() {
+        let snapshot = core_snapshot("cpu0 60 0 20 20 0 0 0 0 0 0", 1000);
+        assert_eq!(snapshot.user_system_ratio(), Some(3.0));
+
+        let snapshot = core_snapshot("cpu0 60 0 0 40 0 0 0 0 0 0", 1000);
+        assert_eq!(snapshot.user_system_ratio(), None);
+    }
+
+    #[test]
+    fn dominant_category() {
+        let snapshot = core_snapshot("cpu0 10 2 45 30 8 3 2 0 5 0", 1000);
+
+        assert_eq!(snapshot.dominant_category(), ("system", 45.0));
+    }
+
+    #[test]
+    fn rounding_modes() {
+        let snapshot = core_snapshot("cpu0 427 0 0 427 0 0 0 0 0 0", 10_000);
+
+        assert_eq!(snapshot.idle_percent(), 42);
+        assert_eq!(snapshot.usage_percent_rounded(RoundingMode::Floor), 42);
+        assert_eq!(snapshot.usage_percent_rounded(RoundingMode::Round), 43);
+        assert_eq!(snapshot.usage_percent_rounded(RoundingMode::Ceil), 43);
+        assert_eq!(snapshot.idle_percent_rounded(RoundingMode::default()), 42);
+        assert_eq!(snapshot.idle_percent_rounded(RoundingMode::Round), 43);
+    }
+
+    #[test]
+    fn usage_percent_u8() {
+        let core = core_snapshot("cpu0 42 0 0 57 0 0 0 0 0 0", 1000);
+        let aggregate = core_snapshot("cpu  250 0 30 120 0 0 0 0 0 0", 1000);
+
+        assert_eq!(core.usage_percent_u8(), 42);
+        assert_eq!(aggregate.usage_percent(), 280.0);
+        assert_eq!(aggregate.usage_percent_u8(), 100);
+    }
+
+    #[test]
+    fn no_overflow() {
+        let before = CoreStats::from_str("cpu  0 0 0 0 90 0 0 0 0 0").unwrap();
+        let after = CoreStats::from_str(&format!("cpu  {0} {0} {0} {0} 40 0 0 0 0 0", u64::MAX)).unwrap();
+        let snapshot = CoreSnapshot::new(before.diff(&after), Duration::from_millis(1000));
+
+        assert_eq!(snapshot.stats.io_wait, 0);
+        assert_eq!(snapshot.idle_percent(), u64::MAX);
+        assert_eq!(snapshot.checked_idle_percent(), Some(u64::MAX));
+        assert_eq!(snapshot.stats.active_time(), u64::MAX);
+        assert!(snapshot.usage_percent().is_finite());
+        assert_eq!(snapshot.compute_percent(), u64::MAX as f64);
+
+        let half = CoreStats::from_str(&format!("cpu0 {0} {0} 0 0 0 0 0 0 0 0", u64::MAX / 2 + 1)).unwrap();
+        assert_eq!(CoreSnapshot::new(half, Duration::from_millis(1000)).compute_percent(), u64::MAX as f64);
+
+        let near_max = CoreSnapshot::new(after.diff(&before), Duration::from_millis(1000));
+        assert_eq!(near_max.idle_percent(), 0);
+        assert_eq!(CoreStats::merge(&[after.clone(), after]).unwrap().idle_time, u64::MAX);
+    }
+
+    #[test]
+    fn effective_utilization() {
+        let snapshot = core_snapshot("cpu0 15 0 5 80 0 0 0 0 0 0", 1000);
+
+        assert_eq!(snapshot.usage_percent(), 20.0);
+        assert_eq!(snapshot.effective_utilization(1_200_000, 2_400_000), 10.0);
+        assert_eq!(snapshot.effective_utilization(1_200_000, 0), 0.0);
+    }
+
+    #[test]
+    fn interned_core_names() {
+        let mut stats_context = context(&[
+            "cpu  10 0 0 10 0 0 0 0 0 0\ncpu0 10 0 0 10 0 0 0 0 0 0",
+            "cpu  30 0 0 20 0 0 0 0 0 0\ncpu0 30 0 0 20 0 0 0 0 0 0",
+            "cpu  60 0 0 40 0 0 0 0 0 0\ncpu0 60 0 0 40 0 0 0 0 0 0",
+        ]);
+        let names: Vec<*const u8> = stats_context.last_stats.iter().map(|core| core.name.as_ptr()).collect();
+
+        for expected in &[(20, 10, 30), (30, 20, 60)] {
+            let snapshots = stats_context.read().unwrap();
+            let deltas: Vec<(&str, u64, u64)> = snapshots.iter()
+                .map(|core| (core.stats.name.as_str(), core.stats.user_processes, core.stats.idle_time))
+                .collect();
+            assert_eq!(deltas, vec![("cpu", expected.0, expected.1), ("cpu0", expected.0, expected.1)]);
+
+            let interned: Vec<*const u8> = stats_context.last_stats.iter().map(|core| core.name.as_ptr()).collect();
+            assert_eq!(interned, names);
+            assert_eq!(stats_context.last_stats[1].user_processes, expected.2);
+        }
+    }
+
+    #[test]
+    fn cores_going_offline() {
+        let mut stats_context = context(&[
+            "cpu  40 0 0 40 0 0 0 0 0 0\ncpu0 10 0 0 10 0 0 0 0 0 0\ncpu1 10 0 0 10 0 0 0 0 0 0\n\
+             cpu2 10 0 0 10 0 0 0 0 0 0\ncpu3 10 0 0 10 0 0 0 0 0 0",
+            "cpu  70 0 0 60 0 0 0 0 0 0\ncpu0 25 0 0 15 0 0 0 0 0 0\ncpu1 15 0 0 25 0 0 0 0 0 0",
+        ]);
+
+        let snapshot = stats_context.read_snapshot().unwrap();
+        assert_eq!(snapshot.went_offline(), ["cpu2", "cpu3"]);
+        assert!(snapshot.newly_online().is_empty());
+
+        let deltas: Vec<(&str, u64, u64)> = snapshot.cores().iter()
+            .map(|core| (core.stats.name.as_str(), core.stats.user_processes, core.stats.idle_time))
+            .collect();
+        assert_eq!(deltas, vec![("cpu", 30, 20), ("cpu0", 15, 5), ("cpu1", 5, 15)]);
+        assert_eq!(stats_context.core_names(false), vec!["cpu0", "cpu1"]);
+    }
+
+    #[test]
+    fn expected_jiffies() {
+        let snapshot = core_snapshot("cpu0 15 0 5 80 0 0 0 0 0 0", 1500);
+
+        assert_eq!(snapshot.expected_jiffies(USER_HZ), 150);
+        assert_eq!(snapshot.expected_jiffies(250), 375);
+        assert_eq!(snapshot.expected_jiffies(0), 0);
+    }
+
+    #[test]
+    fn frequencies_and_usage() {
+        let root = std::env::temp_dir().join(format!("cpu-stats-sysfs-{}", std::process::id()));
+        let cpufreq = root.join("devices/system/cpu/cpu0/cpufreq");
+        std::fs::create_dir_all(&cpufreq).unwrap();
+        std::fs::write(cpufreq.join("scaling_cur_freq"), "1800000\n").unwrap();
+
+        let mut stats_context = context(&[
+            "cpu  0 0 0 0 0 0 0 0 0 0\ncpu0 0 0 0 0 0 0 0 0 0 0\ncpu1 0 0 0 0 0 0 0 0 0 0",
+            "cpu  90 0 0 110 0 0 0 0 0 0\ncpu0 80 0 0 20 0 0 0 0 0 0\ncpu1 10 0 0 90 0 0 0 0 0 0",
+        ]);
+        stats_context.set_sysfs_root(&root);
+        backdate(&mut stats_context);
+        let cores = stats_context.frequencies_and_usage();
+        std::fs::remove_dir_all(&root).unwrap();
+
+        let cores: Vec<(String, u64, u64)> = cores.unwrap().into_iter()
+            .map(|(name, khz, usage)| (name, khz, usage.round() as u64))
+            .collect();
+        assert_eq!(cores, vec![("cpu0".to_string(), 1_800_000, 80), ("cpu1".to_string(), 0, 10)]);
+    }
+
+    #[test]
+    fn exclude_aggregate() {
+        let mut stats_context = context(&[PROC_STAT]);
+        stats_context.set_include_aggregate(false);
+
+        let snapshots = stats_context.read().unwrap();
+        assert_eq!(snapshots.len(), 4);
+        assert!(snapshots.iter().all(|core| !core.is_aggregate()));
+        assert!(stats_context.read_snapshot().unwrap().aggregate().is_some());
+    }
+
+    #[test]
+    fn sort_by_usage() {
+        let mut snapshots = [
+            core_snapshot("cpu2 60 0 0 40 0 0 0 0 0 0", 1000),
+            core_snapshot("cpu1 10 0 0 90 0 0 0 0 0 0", 1000),
+            core_snapshot("cpu3 30 0 0 70 0 0 0 0 0 0", 1000),
+            core_snapshot("cpu0 30 0 0 70 0 0 0 0 0 0", 1000),
+        ];
+        snapshots.sort();
+
+        let order: Vec<(&str, f64)> = snapshots.iter()
+            .map(|core| (core.stats.name.as_str(), core.usage_percent()))
+            .collect();
+        assert_eq!(order, vec![("cpu1", 10.0), ("cpu0", 30.0), ("cpu3", 30.0), ("cpu2", 60.0)]);
+    }
+
+    #[test]
+    fn peak_usage() {
+        let mut stats_context = context(&[
+            "cpu0 0 0 0 0 0 0 0 0 0 0",
+            "cpu0 20 0 0 80 0 0 0 0 0 0",
+            "cpu0 90 0 0 110 0 0 0 0 0 0",
+            "cpu0 100 0 0 200 0 0 0 0 0 0",
+        ]);
+        stats_context.set_clock(stepping_clock(Duration::from_millis(1000)));
+        assert_eq!(stats_context.peak_usage("cpu0"), None);
+
+        for expected in &[20.0, 70.0, 70.0] {
+            stats_context.read().unwrap();
+            assert_eq!(stats_context.peak_usage("cpu0"), Some(*expected));
+        }
+        assert_eq!(stats_context.peak_usage("cpu1"), None);
+    }
+
+    #[test]
+    fn usage_percent_f32() {
+        let snapshot = core_snapshot("cpu0 17 1 5 70 0 0 0 0 0 0", 1300);
+
+        assert!((snapshot.usage_percent_f32() as f64 - snapshot.usage_percent()).abs() < 1e-4);
+    }
+
+    #[test]
+    fn fork_rate() {
+        let mut stats_context = context(&[
+            "cpu0 0 0 0 0 0 0 0 0 0 0\nprocesses 1000",
+            "cpu0 0 0 0 0 0 0 0 0 0 0\nprocesses 1500",
+        ]);
+        stats_context.set_clock(stepping_clock(Duration::from_millis(2000)));
+        stats_context.read().unwrap();
+
+        assert_eq!(stats_context.fork_rate(), 250.0);
+    }
+
+    #[test]
+    fn context_switch_pressure() {
+        let mut stats_context = context(&[
+            "cpu0 0 0 0 0 0 0 0 0 0 0\ncpu1 0 0 0 0 0 0 0 0 0 0\nctxt 100000",
+            "cpu0 0 0 0 0 0 0 0 0 0 0\ncpu1 0 0 0 0 0 0 0 0 0 0\nctxt 116000",
+            "cpu0 0 0 0 0 0 0 0 0 0 0\ncpu1 0 0 0 0 0 0 0 0 0 0\nctxt 156000",
+        ]);
+        stats_context.set_clock(stepping_clock(Duration::from_millis(1000)));
+        assert_eq!(stats_context.context_switch_pressure(), 0.0);
+
+        stats_context.read().unwrap();
+        assert!((stats_context.context_switch_pressure() - 0.8).abs() < 1e-9);
+
+        stats_context.read().unwrap();
+        assert_eq!(stats_context.context_switch_pressure(), 1.0);
+    }
+
+    #[test]
+    fn context_switch_pressure_restricted() {
+        let mut stats_context = context(&[
+            "cpu  0 0 0 0 0 0 0 0 0 0\ncpu0 0 0 0 0 0 0 0 0 0 0\ncpu1 0 0 0 0 0 0 0 0 0 0\ncpu2 0 0 0 0 0 0 0 0 0 0\ncpu3 0 0 0 0 0 0 0 0 0 0\nctxt 100000",
+            "cpu  0 0 0 0 0 0 0 0 0 0\ncpu0 0 0 0 0 0 0 0 0 0 0\ncpu1 0 0 0 0 0 0 0 0 0 0\ncpu2 0 0 0 0 0 0 0 0 0 0\ncpu3 0 0 0 0 0 0 0 0 0 0\nctxt 120000",
+        ]);
+        stats_context.set_clock(stepping_clock(Duration::from_millis(1000)));
+        stats_context.restrict_to_cores([1].iter().copied().collect());
+
+        stats_context.read().unwrap();
+        assert_eq!(stats_context.system_stats().online_cores(), 4);
+        assert!((stats_context.context_switch_pressure() - 0.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn optional_trailing_fields() {
+        let fields = |line: &str| CoreStats::from_str(line).unwrap().fields()
+            .map(|(_, value)| value)
+            .collect::<Vec<u64>>();
+
+        assert_eq!(fields("cpu0 1 2 3 4 5 6 7 8"), vec![1, 2, 3, 4, 5, 6, 7, 8, 0, 0]);
+        assert_eq!(fields("cpu0 1 2 3 4 5 6 7 8 9"), vec![1, 2, 3, 4, 5, 6, 7, 8, 9, 0]);
+        assert_eq!(fields("cpu0 1 2 3 4 5 6 7 8 9 10"), vec![1, 2, 3, 4, 5, 6, 7, 8, 9, 10]);
+        assert_eq!(fields("cpu0 1 2 3 4 5 6 7"), vec![1, 2, 3, 4, 5, 6, 7, 0, 0, 0]);
+
+        assert!(CoreStats::from_str("cpu0 1 2 3 4 5 6 7 x").is_err());
+    }
+
+    #[test]
+    fn normalized_idle_percent() {
+        let aggregate = core_snapshot("cpu  2 0 1 397 0 0 0 0 0 0", 1000);
+        assert_eq!(aggregate.idle_percent(), 397);
+        assert_eq!(aggregate.normalized_idle_percent(4), 99.25);
+        assert_eq!(aggregate.normalized_idle_percent(0), 0.0);
+
+        let core = core_snapshot("cpu0 2 0 1 97 0 0 0 0 0 0", 1000);
+        assert_eq!(core.normalized_idle_percent(4), 97.0);
+    }
+
+    #[test]
+    fn reordered_cores() {
+        let mut stats_context = context(&[
+            "cpu  30 0 0 0 0 0 0 0 0 0\ncpu0 10 0 0 0 0 0 0 0 0 0\ncpu1 20 0 0 0 0 0 0 0 0 0",
+            "cpu1 25 0 0 0 0 0 0 0 0 0\ncpu  65 0 0 0 0 0 0 0 0 0\ncpu0 40 0 0 0 0 0 0 0 0 0",
+            "cpu0 41 0 0 0 0 0 0 0 0 0\ncpu1 35 0 0 0 0 0 0 0 0 0\ncpu  76 0 0 0 0 0 0 0 0 0",
+        ]);
+
+        for expected in &[vec![("cpu1", 5), ("cpu", 35), ("cpu0", 30)], vec![("cpu0", 1), ("cpu1", 10), ("cpu", 11)]] {
+            let snapshot = stats_context.read_snapshot().unwrap();
+            assert!(snapshot.newly_online().is_empty());
+            assert!(snapshot.went_offline().is_empty());
+
+            let deltas: Vec<(&str, u64)> = snapshot.cores().iter()
+                .map(|core| (core.stats.name.as_str(), core.stats.user_processes))
+                .collect();
+            assert_eq!(&deltas, expected);
+        }
+    }
+
+    #[test]
+    fn read_since_baseline() {
+        let mut stats_context = context(&[
+            "cpu0 10 0 0 10 0 0 0 0 0 0",
+            "cpu0 30 0 0 20 0 0 0 0 0 0",
+            "cpu0 70 0 0 50 0 0 0 0 0 0",
+        ]);
+        stats_context.set_clock(stepping_clock(Duration::from_millis(1000)));
+        assert_eq!(stats_context.read_since_baseline().unwrap_err().kind(), io::ErrorKind::InvalidInput);
+
+        stats_context.mark_baseline();
+        assert_eq!(stats_context.read().unwrap()[0].stats.user_processes, 20);
+
+        let since = stats_context.read_since_baseline().unwrap();
+        assert_eq!(since.len(), 1);
+        assert_eq!(since[0].stats.user_processes, 60);
+        assert_eq!(since[0].stats.idle_time, 40);
+        assert_eq!(since[0].period(), Duration::from_millis(2000));
+
+        assert_eq!(stats_context.read().unwrap()[0].stats.user_processes, 40);
+    }
+
+    #[test]
+    fn likely_virtualized() {
+        assert!(!context(&[PROC_STAT]).likely_virtualized());
+
+        let mut stats_context = context(&[
+            "cpu  10 0 0 10 0 0 0 7 0 0\ncpu0 5 0 0 5 0 0 0 0 0 0\ncpu1 5 0 0 5 0 0 0 7 0 0",
+            "cpu0 5 0 0 5 0 0 0 0 0 0",
+        ]);
+        assert!(stats_context.likely_virtualized());
+        stats_context.read().unwrap();
+        assert!(stats_context.likely_virtualized());
+    }
+
+    #[test]
+    fn percent_conventions() {
+        assert_eq!(per_core_to_system(100.0, 4), Some(25.0));
+        assert_eq!(per_core_to_system(400.0, 4), Some(100.0));
+        assert_eq!(system_to_per_core(25.0, 4), Some(100.0));
+        assert_eq!(system_to_per_core(per_core_to_system(130.0, 4).unwrap(), 4), Some(130.0));
+
+        assert_eq!(per_core_to_system(100.0, 0), None);
+        assert_eq!(system_to_per_core(100.0, 0), None);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn save_and_load_baseline() {
+        let path = std::env::temp_dir().join(format!("cpu-stats-baseline-{}.json", std::process::id()));
+        let mut stats_context = context(&[
+            "cpu  20 0 0 20 0 0 0 0 0 0\ncpu0 20 0 0 20 0 0 0 0 0 0",
+            "cpu  50 0 0 30 0 0 0 0 0 0\ncpu0 50 0 0 30 0 0 0 0 0 0",
+            "cpu  90 0 0 70 0 0 0 0 0 0\ncpu0 90 0 0 70 0 0 0 0 0 0",
+        ]);
+        stats_context.save_baseline(&path).unwrap();
+        stats_context.read().unwrap();
+
+        let mut later = context(&["cpu  90 0 0 70 0 0 0 0 0 0\ncpu0 90 0 0 70 0 0 0 0 0 0"]);
+        later.load_baseline(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(later.last_stats, parse_stats("cpu  20 0 0 20 0 0 0 0 0 0\ncpu0 20 0 0 20 0 0 0 0 0 0").unwrap());
+
+        let deltas: Vec<(String, u64, u64)> = later.read().unwrap().into_iter()
+            .map(|core| (core.stats.name, core.stats.user_processes, core.stats.idle_time))
+            .collect();
+        assert_eq!(deltas, vec![("cpu".to_string(), 70, 50), ("cpu0".to_string(), 70, 50)]);
+    }
+
+    #[test]
+    fn adaptive_sample() {
+        let mut stats_context = context(&[
+            "cpu  0 0 0 0 0 0 0 0 0 0\ncpu0 0 0 0 0 0 0 0 0 0 0",
+            "cpu  50 0 0 50 0 0 0 0 0 0\ncpu0 50 0 0 50 0 0 0 0 0 0",
+            "cpu  100 0 0 100 0 0 0 0 0 0\ncpu0 100 0 0 100 0 0 0 0 0 0",
+            "cpu  150 0 0 150 0 0 0 0 0 0\ncpu0 150 0 0 150 0 0 0 0 0 0",
+            "cpu  245 0 0 155 0 0 0 0 0 0\ncpu0 245 0 0 155 0 0 0 0 0 0",
+            "cpu  250 0 0 250 0 0 0 0 0 0\ncpu0 250 0 0 250 0 0 0 0 0 0",
+        ]);
+        stats_context.set_clock(stepping_clock(Duration::from_millis(1000)));
+        let (min, max) = (Duration::from_millis(1), Duration::from_millis(4));
+
+        let mut intervals = Vec::new();
+        for _ in 0..5 {
+            stats_context.adaptive_sample(min, max).unwrap();
+            intervals.push(stats_context.adaptive_interval().unwrap().as_millis());
+        }
+        assert_eq!(intervals, vec![1, 2, 4, 2, 1]);
+    }
+
+    #[test]
+    fn minimum_fields() {
+        let strict = ReadOptions { strict: true, ..ReadOptions::default() };
+
+        let cores = parse_cores("cpu0 1 2 3 4\n", strict).unwrap();
+        assert_eq!(cores[0].fields().map(|(_, value)| value).collect::<Vec<u64>>(), vec![1, 2, 3, 4, 0, 0, 0, 0, 0, 0]);
+
+        let err = parse_cores("cpu0 1 2 3\n", strict).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        match err.into_inner().unwrap().downcast::<CpuStatsError>() {
+            Ok(err) => assert!(matches!(*err, CpuStatsError::Parse(msg) if msg == "missing idle field")),
+            Err(_) => panic!("expected CpuStatsError"),
+        }
+        assert!(parse_cores("cpu0 1 2 3\n", ReadOptions::default()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn idle_delta_pp() {
+        let previous = core_snapshot("cpu0 30 0 0 70 0 0 0 0 0 0", 1000);
+        let current = core_snapshot("cpu0 50 0 0 50 0 0 0 0 0 0", 1000);
+
+        assert_eq!(current.idle_delta_pp(&previous), Some(-20.0));
+        assert_eq!(previous.idle_delta_pp(&current), Some(20.0));
+        assert_eq!(current.idle_delta_pp(&core_snapshot("cpu1 30 0 0 70 0 0 0 0 0 0", 1000)), None);
+    }
+
+    #[test]
+    fn feed() {
+        let mut stats_context = context(&["cpu0 0 0 0 0 0 0 0 0 0 0"]);
+        stats_context.feed("cpu  10 0 0 30 0 0 0 0 0 0\ncpu0 10 0 0 30 0 0 0 0 0 0\n", Duration::from_secs(1)).unwrap();
+
+        let snapshots = stats_context.feed(
+            "cpu  60 0 10 130 0 0 0 0 0 0\ncpu0 60 0 10 130 0 0 0 0 0 0\n",
+            Duration::from_secs(2),
+        ).unwrap();
+        let deltas: Vec<(&str, u64, u64, u64)> = snapshots.iter()
+            .map(|core| (core.stats.name.as_str(), core.stats.user_processes, core.stats.system_processes, core.stats.idle_time))
+            .collect();
+        assert_eq!(deltas, vec![("cpu", 50, 10, 100), ("cpu0", 50, 10, 100)]);
+        assert!(snapshots.iter().all(|core| core.period() == Duration::from_secs(2) && core.usage_percent() == 30.0));
+        assert_eq!(stats_context.last_period(), Duration::from_secs(2));
+    }
+
+    #[test]
+    fn power_proxy() {
+        let snapshot = core_snapshot("cpu0 40 0 10 50 0 0 0 0 0 0", 1000);
+
+        assert_eq!(snapshot.power_proxy(2_000_000, 2_000_000, 0.25), 0.125);
+        assert_eq!(snapshot.power_proxy(1_000_000, 2_000_000, 0.25), 0.015625);
+        assert_eq!(snapshot.power_proxy(1_000_000, 0, 0.25), 0.0);
+    }
+
+    #[test]
+    fn per_core_only() {
+        let mut stats_context = CPUStatsContext::per_core_only_from_source(source::Sequence::new(&[PROC_STAT])).unwrap();
+        assert_eq!(stats_context.last_stats.len(), 4);
+        assert!(stats_context.last_stats.iter().all(|core| !core.is_aggregate()));
+
+        let snapshot = stats_context.read_snapshot().unwrap();
+        assert_eq!(snapshot.core_count(), 4);
+        assert!(snapshot.aggregate().is_none());
+        assert!(stats_context.read().unwrap().iter().all(|core| !core.is_aggregate()));
+        assert_eq!(stats_context.system_stats().processes, 4321);
+    }
+
+    #[test]
+    fn core_id() {
+        let by_id: HashMap<i32, CoreSnapshot> = IntoIterator::into_iter([
+            core_snapshot("cpu  40 0 10 50 0 0 0 0 0 0", 1000),
+            core_snapshot("cpu0 10 0 0 90 0 0 0 0 0 0", 1000),
+            core_snapshot("cpu12 30 0 10 60 0 0 0 0 0 0", 1000),
+        ]).map(|core| (core.core_id(), core)).collect();
+
+        assert!(by_id[&-1].is_aggregate());
+        assert_eq!(by_id[&0].stats.name, "cpu0");
+        assert_eq!(by_id[&12].stats.name, "cpu12");
+        assert!(!by_id.contains_key(&1));
+    }
+
+    #[test]
+    fn is_first_read() {
+        let mut stats_context = CPUStatsContext::from_source(source::Sequence::new(&[PROC_STAT, PROC_STAT, PROC_STAT])).unwrap();
+
+        assert!(stats_context.read_snapshot().unwrap().is_first_read());
+        assert!(!stats_context.read_snapshot().unwrap().is_first_read());
+        assert!(!stats_context.read_snapshot().unwrap().is_first_read());
+        assert!(!Snapshot::new(Vec::new()).is_first_read());
+    }
+
+    #[test]
+    fn delta_fields() {
+        let later = "\
+cpu  400 20 300 8000 40 10 5 0 0 0
+cpu0 117 7 80 2011 13 5 2 4 3 1
+cpu1 100 5 75 2000 10 2 1 0 0 0
+cpu2 100 5 75 2000 10 3 2 0 0 0
+cpu3 100 5 75 2000 10 2 1 0 0 0
+";
+        let mut stats_context = CPUStatsContext::from_source(source::Sequence::new(&[PROC_STAT, later])).unwrap();
+        stats_context.set_include_aggregate(false);
+        let snapshots = stats_context.read().unwrap();
+        let delta = snapshots[0].delta();
+
+        assert_eq!(delta.name, "cpu0");
+        assert_eq!(delta.user_processes, 17);
+        assert_eq!(delta.nice_processes, 2);
+        assert_eq!(delta.system_processes, 5);
+        assert_eq!(delta.idle_time, 11);
+        assert_eq!(delta.io_wait, 3);
+        assert_eq!(delta.irq, 2);
+        assert_eq!(delta.soft_irq, 1);
+        assert_eq!(delta.steal_time, 4);
+        assert_eq!(delta.guest, 3);
+        assert_eq!(delta.guest_nice, 1);
+        assert!(snapshots[1..].iter().all(|core| core.delta().active_time() == 0));
+    }
+
+    #[test]
+    fn check_monotonic() {
+        let stats_context = CPUStatsContext::from_source(source::Sequence::new(&[PROC_STAT])).unwrap();
+        let next = [
+            CoreStats::from_str("cpu  410 20 300 8010 40 10 5 0 0 0").unwrap(),
+            CoreStats::from_str("cpu0 110 5 75 2000 10 3 1 0 0 0").unwrap(),
+            CoreStats::from_str("cpu1 100 5 60 2010 10 2 1 0 0 0").unwrap(),
+            CoreStats::from_str("cpu9 0 0 0 0 0 0 0 0 0 0").unwrap(),
+        ];
+
+        assert_eq!(stats_context.check_monotonic(&next), vec!["cpu1"]);
+        assert!(stats_context.check_monotonic(&next[..2]).is_empty());
+    }
+
+    #[test]
+    fn last_aggregate() {
+        let stats_context = CPUStatsContext::from_source(source::Sequence::new(&[PROC_STAT])).unwrap();
+        let aggregate = stats_context.last_aggregate().unwrap();
+
+        assert_eq!(aggregate.name, "cpu");
+        assert_eq!(aggregate.user_processes, 400);
+        assert_eq!(aggregate.idle_time, 8000);
+
+        let per_core = CPUStatsContext::per_core_only_from_source(source::Sequence::new(&[PROC_STAT])).unwrap();
+        assert!(per_core.last_aggregate().is_none());
+    }
+
+    #[test]
+    fn replaced_last_stats_same_length() {
+        let mut stats_context = CPUStatsContext::from_source(source::Sequence::new(&["cpu0 0 0 0 0 0 0 0 0 0 0\ncpu1 0 0 0 0 0 0 0 0 0 0\n"])).unwrap();
+        stats_context.last_stats = vec![
+            CoreStats::from_str("cpu2 10 0 0 10 0 0 0 0 0 0").unwrap(),
+            CoreStats::from_str("cpu3 10 0 0 10 0 0 0 0 0 0").unwrap(),
+        ];
+
+        let later = [
+            CoreStats::from_str("cpu2 20 0 0 20 0 0 0 0 0 0").unwrap(),
+            CoreStats::from_str("cpu3 5 0 0 20 0 0 0 0 0 0").unwrap(),
+        ];
+        assert_eq!(stats_context.check_monotonic(&later), vec!["cpu3"]);
+
+        let snapshots = stats_context.feed("cpu2 20 0 0 20 0 0 0 0 0 0\ncpu3 20 0 0 20 0 0 0 0 0 0\n", Duration::from_secs(1)).unwrap();
+        assert_eq!(snapshots.len(), 2);
+        assert_eq!(snapshots[0].stats.user_processes, 10);
+    }
+
+    #[test]
+    fn adaptive_sample_invalid_interval() {
+        let mut stats_context = CPUStatsContext::from_source(source::Sequence::new(&[PROC_STAT])).unwrap();
+
+        let err = stats_context.adaptive_sample(Duration::ZERO, Duration::from_millis(10)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        let err = stats_context.adaptive_sample(Duration::from_millis(10), Duration::from_millis(5)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(stats_context.adaptive_interval(), None);
     }
 }
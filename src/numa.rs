@@ -0,0 +1,91 @@
+///
+/// NUMA node topology from sysfs
+///
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+///
+/// Map of core name to NUMA node from the `nodeN/cpulist` files of sysfs mounted at `sysfs_root`.
+///
+/// Empty if the kernel exposes no NUMA nodes.
+///
+pub fn read_numa_nodes<P: AsRef<Path>>(sysfs_root: P) -> io::Result<HashMap<String, u32>> {
+    let entries = match fs::read_dir(sysfs_root.as_ref().join("devices/system/node")) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(HashMap::new()),
+        Err(err) => return Err(err),
+    };
+
+    let mut nodes = HashMap::new();
+    for entry in entries {
+        let entry = entry?;
+        let node = match entry.file_name().to_str().and_then(|name| name.strip_prefix("node")) {
+            Some(index) => match index.parse::<u32>() {
+                Ok(node) => node,
+                Err(_) => continue,
+            },
+            None => continue,
+        };
+        let list = fs::read_to_string(entry.path().join("cpulist"))?;
+        for core in parse_cpu_list(&list)? {
+            nodes.insert(format!("cpu{}", core), node);
+        }
+    }
+    Ok(nodes)
+}
+
+///
+/// Parse a kernel cpu list such as `0-3,8,10-11` into core indexes.
+///
+pub fn parse_cpu_list(list: &str) -> io::Result<Vec<usize>> {
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, format!("invalid cpu list `{}`", list.trim()));
+    let mut cores = Vec::new();
+    for range in list.trim().split(',').filter(|range| !range.is_empty()) {
+        let mut bounds = range.splitn(2, '-').map(|bound| bound.parse::<usize>().map_err(|_| invalid()));
+        let start = bounds.next().ok_or_else(invalid)??;
+        let end = bounds.next().transpose()?.unwrap_or(start);
+        cores.extend(start..=end);
+    }
+    Ok(cores)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+    use std::time::Duration;
+    use crate::{CoreSnapshot, CoreStats, Snapshot};
+
+    const SYSFS: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/sys");
+
+    #[test]
+    fn parse_cpu_list() {
+        assert_eq!(super::parse_cpu_list("0-3,8,10-11\n").unwrap(), vec![0, 1, 2, 3, 8, 10, 11]);
+        assert!(super::parse_cpu_list("").unwrap().is_empty());
+        assert!(super::parse_cpu_list("0-x").is_err());
+    }
+
+    #[test]
+    fn group_by_numa_node() {
+        let nodes = read_numa_nodes(SYSFS).unwrap();
+        assert_eq!(nodes.len(), 6);
+
+        let snapshot = Snapshot::new(["cpu", "cpu0", "cpu1", "cpu2", "cpu3", "cpu4", "cpu5", "cpu6"].iter()
+            .map(|name| {
+                let stats = CoreStats::from_str(&format!("{} 1 0 0 1 0 0 0 0 0 0", name)).unwrap();
+                CoreSnapshot::new(stats, Duration::from_millis(1000))
+            })
+            .collect());
+        let grouped: Vec<(u32, Vec<&str>)> = snapshot.by_numa_node(&nodes).into_iter()
+            .map(|(node, cores)| (node, cores.iter().map(|core| core.stats.name.as_str()).collect()))
+            .collect();
+
+        assert_eq!(grouped, vec![
+            (0, vec!["cpu0", "cpu1", "cpu4"]),
+            (1, vec!["cpu2", "cpu3", "cpu5"]),
+        ]);
+        assert!(read_numa_nodes("/nonexistent").unwrap().is_empty());
+    }
+}
@@ -0,0 +1,71 @@
+///
+/// Export of usage to the OpenTelemetry metrics API
+///
+use opentelemetry::metrics::Gauge;
+use opentelemetry::KeyValue;
+
+use crate::Snapshot;
+
+///
+/// Attribute identifying the core a gauge value was recorded for
+///
+pub const CORE_ATTRIBUTE: &str = "cpu.core";
+
+impl Snapshot {
+    ///
+    /// Record the usage of every core, including the aggregate, on an OpenTelemetry gauge.
+    ///
+    /// Each value is tagged with a `cpu.core` attribute holding the core name.
+    ///
+    pub fn record_otel(&self, gauge: &Gauge<f64>) {
+        for core in self.cores() {
+            gauge.record(core.usage_percent(), &[KeyValue::new(CORE_ATTRIBUTE, core.stats.name.clone())]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry::metrics::MeterProvider;
+    use opentelemetry_sdk::metrics::data::{AggregatedMetrics, MetricData};
+    use opentelemetry_sdk::metrics::{InMemoryMetricExporter, PeriodicReader, SdkMeterProvider};
+    use crate::core_snapshot;
+
+    #[test]
+    fn record_otel() {
+        let exporter = InMemoryMetricExporter::default();
+        let provider = SdkMeterProvider::builder()
+            .with_reader(PeriodicReader::builder(exporter.clone()).build())
+            .build();
+        let gauge = provider.meter("cpu-stats").f64_gauge("cpu.usage").build();
+
+        Snapshot::new(vec![
+            core_snapshot("cpu  80 0 20 100 0 0 0 0 0 0", 1000),
+            core_snapshot("cpu0 50 0 10 40 0 0 0 0 0 0", 1000),
+            core_snapshot("cpu1 30 0 10 60 0 0 0 0 0 0", 1000),
+        ]).record_otel(&gauge);
+        provider.force_flush().unwrap();
+
+        let mut recorded = Vec::new();
+        for resource in exporter.get_finished_metrics().unwrap() {
+            for scope in resource.scope_metrics() {
+                for metric in scope.metrics() {
+                    assert_eq!(metric.name(), "cpu.usage");
+                    if let AggregatedMetrics::F64(MetricData::Gauge(gauge)) = metric.data() {
+                        for point in gauge.data_points() {
+                            let attributes: Vec<(String, String)> = point.attributes()
+                                .map(|attribute| (attribute.key.to_string(), attribute.value.to_string()))
+                                .collect();
+                            recorded.push((attributes, point.value()));
+                        }
+                    }
+                }
+            }
+        }
+        recorded.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let attribute = |name: &str| vec![(CORE_ATTRIBUTE.to_string(), name.to_string())];
+        assert_eq!(recorded, vec![(attribute("cpu"), 100.0), (attribute("cpu0"), 60.0), (attribute("cpu1"), 40.0)]);
+    }
+}
@@ -0,0 +1,7 @@
+///
+/// Commonly used types and traits, `use cpu_stats::prelude::*;` to import them all
+///
+pub use crate::{
+    Clock, CoreDelta, CoreSnapshot, CoreStats, CPUStatsContext, CpuStatsError, RoundingMode,
+    Snapshot, StatsSource, SystemStats, USER_HZ,
+};
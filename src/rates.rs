@@ -0,0 +1,73 @@
+///
+/// Counters converted to per second rates
+///
+use std::time::Duration;
+
+use crate::CoreStats;
+
+///
+/// Jiffies per second spent in each category, the same layout as `CoreStats`.
+///
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct CoreRates {
+    pub name: String,
+    pub user_processes: f64,
+    pub nice_processes: f64,
+    pub system_processes: f64,
+    pub idle_time: f64,
+    pub io_wait: f64,
+    pub irq: f64,
+    pub soft_irq: f64,
+    pub steal_time: f64,
+    pub guest: f64,
+    pub guest_nice: f64,
+}
+
+impl CoreStats {
+    ///
+    /// Rate of each counter of a `CoreDelta` in jiffies per second over the period, all zero
+    /// for a zero period.
+    ///
+    pub fn rates_per_second(&self, period: Duration) -> CoreRates {
+        let seconds = period.as_secs_f64();
+        let rate = |delta: u64| if seconds > 0.0 { delta as f64 / seconds } else { 0.0 };
+
+        CoreRates {
+            name: self.name.clone(),
+            user_processes: rate(self.user_processes),
+            nice_processes: rate(self.nice_processes),
+            system_processes: rate(self.system_processes),
+            idle_time: rate(self.idle_time),
+            io_wait: rate(self.io_wait),
+            irq: rate(self.irq),
+            soft_irq: rate(self.soft_irq),
+            steal_time: rate(self.steal_time),
+            guest: rate(self.guest),
+            guest_nice: rate(self.guest_nice),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn rates_per_second() {
+        let delta = CoreStats::from_str("cpu0 40 2 10 140 6 0 4 0 0 0").unwrap();
+        let rates = delta.rates_per_second(Duration::from_secs(2));
+
+        assert_eq!(rates, CoreRates {
+            name: "cpu0".to_string(),
+            user_processes: 20.0,
+            nice_processes: 1.0,
+            system_processes: 5.0,
+            idle_time: 70.0,
+            io_wait: 3.0,
+            soft_irq: 2.0,
+            ..CoreRates::default()
+        });
+        assert_eq!(delta.rates_per_second(Duration::from_secs(0)), CoreRates { name: "cpu0".to_string(), ..CoreRates::default() });
+    }
+}
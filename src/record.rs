@@ -0,0 +1,85 @@
+///
+/// Compact fixed size binary records for archiving snapshots
+///
+use std::convert::TryInto;
+use std::time::Duration;
+
+use crate::{CoreSnapshot, CoreStats, CpuStatsError};
+
+///
+/// Size in bytes of an encoded snapshot
+///
+pub const RECORD_SIZE: usize = 48;
+
+impl CoreSnapshot {
+    ///
+    /// Encode as a fixed size little-endian record.
+    ///
+    /// The record is the `core_id` as an `i32` (-1 for the aggregate), the ten deltas as `u32`s
+    /// in stats file order and the period in milliseconds as a `u32`. Values too large for a
+    /// `u32` are saturated. Only the index of the name is stored so names not of the form `cpuN`
+    /// do not survive a round trip.
+    ///
+    pub fn to_bytes(&self) -> [u8; RECORD_SIZE] {
+        let mut record = [0u8; RECORD_SIZE];
+        record[0..4].copy_from_slice(&self.core_id().to_le_bytes());
+
+        let values = self.stats.fields().map(|(_, value)| value).chain(Some(self.period_ms()));
+        for (chunk, value) in record[4..].chunks_exact_mut(4).zip(values) {
+            let value = value.min(u32::MAX as u64) as u32;
+            chunk.copy_from_slice(&value.to_le_bytes());
+        }
+        record
+    }
+
+    ///
+    /// Decode a record produced by `to_bytes`.
+    ///
+    pub fn from_bytes(record: &[u8]) -> Result<Self, CpuStatsError> {
+        if record.len() != RECORD_SIZE {
+            return Err(CpuStatsError::Parse(format!(
+                "expected {} byte record, found {} bytes", RECORD_SIZE, record.len()
+            )));
+        }
+
+        let mut words = record.chunks_exact(4).map(|chunk| chunk.try_into().unwrap());
+        let index = i32::from_le_bytes(words.next().unwrap());
+        let mut values = words.map(|word| u32::from_le_bytes(word) as u64);
+        let mut next = || values.next().unwrap();
+
+        let name = if index < 0 { String::from("cpu") } else { format!("cpu{}", index) };
+        let stats = CoreStats {
+            name,
+            user_processes: next(),
+            nice_processes: next(),
+            system_processes: next(),
+            idle_time: next(),
+            io_wait: next(),
+            irq: next(),
+            soft_irq: next(),
+            steal_time: next(),
+            guest: next(),
+            guest_nice: next(),
+        };
+        Ok(Self::new(stats, Duration::from_millis(next())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core_snapshot;
+
+    #[test]
+    fn round_trip() {
+        for line in &["cpu  1 2 3 4 5 6 7 8 9 10", "cpu12 100 0 25 875 3 1 0 2 0 0"] {
+            let snapshot = core_snapshot(line, 1003);
+            let record = snapshot.to_bytes();
+
+            assert_eq!(record.len(), RECORD_SIZE);
+            assert_eq!(CoreSnapshot::from_bytes(&record).unwrap(), snapshot);
+        }
+
+        assert!(CoreSnapshot::from_bytes(&[0u8; 12]).is_err());
+    }
+}
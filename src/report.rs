@@ -0,0 +1,80 @@
+///
+/// Owned reports of a read for logging and time series storage
+///
+use std::time::{Duration, SystemTime};
+
+use crate::Snapshot;
+
+///
+/// Percentages of a single core within a report
+///
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CoreReport {
+    pub name: String,
+    pub usage: f64,
+    pub idle: f64,
+    pub io_wait: f64,
+}
+
+///
+/// Self contained summary of a snapshot along with the wall clock time it was taken.
+///
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SnapshotReport {
+    pub timestamp: SystemTime,
+    ///
+    /// Period covered by the snapshot, zero if it has no cores
+    ///
+    pub period: Duration,
+    ///
+    /// Every core in the snapshot, including the aggregate
+    ///
+    pub cores: Vec<CoreReport>,
+}
+
+impl Snapshot {
+    ///
+    /// Owned report of the percentages of each core, stamped with the given time.
+    ///
+    pub fn into_report(&self, timestamp: SystemTime) -> SnapshotReport {
+        SnapshotReport {
+            timestamp,
+            period: self.cores().first().map_or(Duration::from_secs(0), |core| core.period()),
+            cores: self.cores().iter()
+                .map(|core| CoreReport {
+                    name: core.stats.name.clone(),
+                    usage: core.usage_percent(),
+                    idle: core.percent_of_period(core.stats.idle_time),
+                    io_wait: core.percent_of_period(core.stats.io_wait),
+                })
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core_snapshot;
+
+    #[test]
+    fn into_report() {
+        let timestamp = SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+        let snapshot = Snapshot::new(vec![
+            core_snapshot("cpu  100 0 20 260 20 0 0 0 0 0", 2000),
+            core_snapshot("cpu0 70 0 10 110 10 0 0 0 0 0", 2000),
+            core_snapshot("cpu1 30 0 10 150 10 0 0 0 0 0", 2000),
+        ]);
+        let report = snapshot.into_report(timestamp);
+
+        assert_eq!(report.timestamp, timestamp);
+        assert_eq!(report.period, Duration::from_millis(2000));
+        assert_eq!(report.cores, vec![
+            CoreReport { name: "cpu".to_string(), usage: 60.0, idle: 130.0, io_wait: 10.0 },
+            CoreReport { name: "cpu0".to_string(), usage: 40.0, idle: 55.0, io_wait: 5.0 },
+            CoreReport { name: "cpu1".to_string(), usage: 20.0, idle: 75.0, io_wait: 5.0 },
+        ]);
+    }
+}
@@ -0,0 +1,854 @@
+///
+/// Helpers over the complete set of core snapshots from a read
+///
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+use std::ops::Deref;
+
+use std::time::Duration;
+
+use crate::{jiffies_to_duration, CoreSnapshot};
+
+///
+/// Overall state of the system by usage, see `Snapshot::classify`
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SystemState {
+    Idle,
+    Normal,
+    Busy,
+    Saturated,
+}
+
+///
+/// System usage (0-100) at which each state begins, used by `Snapshot::classify_with`
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StateThresholds {
+    ///
+    /// Usage at which the system is normal rather than idle, 10 by default
+    ///
+    pub normal: f64,
+    ///
+    /// Usage at which the system is busy, 70 by default
+    ///
+    pub busy: f64,
+    ///
+    /// Usage at which the system is saturated, 90 by default
+    ///
+    pub saturated: f64,
+}
+
+impl Default for StateThresholds {
+    fn default() -> Self {
+        Self {
+            normal: 10.0,
+            busy: 70.0,
+            saturated: 90.0,
+        }
+    }
+}
+
+///
+/// All core snapshots produced by a single read.
+///
+pub struct Snapshot {
+    cores: Vec<CoreSnapshot>,
+    newly_online: Vec<String>,
+    went_offline: Vec<String>,
+    first_read: bool,
+}
+
+impl Snapshot {
+    pub fn new(cores: Vec<CoreSnapshot>) -> Self {
+        Snapshot::with_changes(cores, Vec::new(), Vec::new(), false)
+    }
+
+    pub(crate) fn with_changes(cores: Vec<CoreSnapshot>, newly_online: Vec<String>, went_offline: Vec<String>, first_read: bool) -> Self {
+        Self {
+            cores,
+            newly_online,
+            went_offline,
+            first_read,
+        }
+    }
+
+    ///
+    /// Snapshots of each core, including the aggregate
+    ///
+    pub fn cores(&self) -> &[CoreSnapshot] {
+        &self.cores
+    }
+
+    pub fn into_cores(self) -> Vec<CoreSnapshot> {
+        self.cores
+    }
+
+    ///
+    /// Names of cores that appeared since the previous read
+    ///
+    pub fn newly_online(&self) -> &[String] {
+        &self.newly_online
+    }
+
+    ///
+    /// Names of cores that were in the previous read but are now missing
+    ///
+    pub fn went_offline(&self) -> &[String] {
+        &self.went_offline
+    }
+
+    ///
+    /// This is the first read since the context was created.
+    ///
+    /// The period of the first read runs from construction rather than a previous read so is
+    /// often unrepresentative, callers may want to skip it.
+    ///
+    pub fn is_first_read(&self) -> bool {
+        self.first_read
+    }
+
+    ///
+    /// Core snapshots ordered by core index with the aggregate first.
+    ///
+    /// The stats file is normally in index order but this is not guaranteed.
+    ///
+    pub fn sorted_by_index(self) -> Vec<CoreSnapshot> {
+        let mut cores = self.cores;
+        cores.sort_by_key(|core| core.stats.index());
+        cores
+    }
+
+    ///
+    /// Snapshot of the aggregate of all cores, if it was read
+    ///
+    pub fn aggregate(&self) -> Option<&CoreSnapshot> {
+        self.cores.iter().find(|core| core.is_aggregate())
+    }
+
+    ///
+    /// Number of individual cores, excluding the aggregate
+    ///
+    pub fn core_count(&self) -> usize {
+        self.per_core().count()
+    }
+
+    ///
+    /// Snapshots excluding the aggregate
+    ///
+    fn per_core(&self) -> impl Iterator<Item = &CoreSnapshot> {
+        self.cores.iter().filter(|core| !core.is_aggregate())
+    }
+
+    ///
+    /// Individual core with the lowest usage, ties go to the lowest core index.
+    ///
+    pub fn least_busy_core(&self) -> Option<&CoreSnapshot> {
+        self.per_core().min_by(|a, b| {
+            a.usage_percent().partial_cmp(&b.usage_percent())
+                .unwrap_or(Ordering::Equal)
+                .then_with(|| a.stats.index().cmp(&b.stats.index()))
+        })
+    }
+
+    ///
+    /// Active (non-idle, non-iowait) jiffies summed across all cores, excluding the aggregate.
+    ///
+    pub fn total_active_jiffies(&self) -> u64 {
+        self.per_core().fold(0, |total: u64, core| total.saturating_add(core.stats.active_time()))
+    }
+
+    ///
+    /// Real CPU time spent active summed across all cores, excluding the aggregate.
+    ///
+    /// The total CPU-seconds consumed over the period, given the clock tick (ticks per second).
+    ///
+    pub fn total_busy_duration(&self, clock_tick: u64) -> Duration {
+        jiffies_to_duration(self.total_active_jiffies(), clock_tick)
+    }
+
+    ///
+    /// Real CPU time spent idle summed across all cores, excluding the aggregate.
+    ///
+    /// I/O wait is not counted as idle, see `CoreStats::idle_duration`.
+    ///
+    pub fn total_idle_duration(&self, clock_tick: u64) -> Duration {
+        let idle = self.per_core().fold(0, |total: u64, core| total.saturating_add(core.stats.idle_time));
+        jiffies_to_duration(idle, clock_tick)
+    }
+
+    ///
+    /// Aggregate active jiffies minus the sum of the per-core active jiffies.
+    ///
+    /// Should be close to zero, a large value indicates missing cores or bad parsing. The
+    /// difference is saturated to the range of an `i64`. `None` if there is no aggregate.
+    ///
+    pub fn aggregate_vs_sum_discrepancy(&self) -> Option<i64> {
+        let aggregate = self.aggregate()?.stats.active_time();
+        let difference = aggregate as i128 - self.total_active_jiffies() as i128;
+        Some(difference.clamp(i64::MIN as i128, i64::MAX as i128) as i64)
+    }
+
+    ///
+    /// Usage of the whole system as 0-100, the aggregate active time as a share of its active,
+    /// idle and I/O wait time.
+    ///
+    /// The aggregate covers every online core, so this is unaffected by cores missing from the
+    /// snapshot, eg when restricted to an affinity set, and does not depend on the period.
+    /// `None` if there is no aggregate or it accumulated no time.
+    ///
+    pub fn aggregate_usage(&self) -> Option<f64> {
+        let stats = &self.aggregate()?.stats;
+        let active = stats.active_time();
+        let total = active.saturating_add(stats.idle_time).saturating_add(stats.io_wait);
+        match total {
+            0 => None,
+            total => Some(active as f64 * 100.0 / total as f64),
+        }
+    }
+
+    ///
+    /// Split of the aggregate busy time as `(kernel, user)` percentages summing to 100.
+    ///
+    /// Kernel is system + irq + softirq, user is user + nice; steal and guest time are not counted.
+    /// Both are 0 if there is no aggregate or it spent no time in either.
+    ///
+    pub fn kernel_user_split(&self) -> (f64, f64) {
+        let stats = match self.aggregate() {
+            Some(aggregate) => &aggregate.stats,
+            None => return (0.0, 0.0),
+        };
+        let kernel = stats.system_processes.saturating_add(stats.irq).saturating_add(stats.soft_irq) as f64;
+        let user = stats.user_processes.saturating_add(stats.nice_processes) as f64;
+        let busy = kernel + user;
+        if busy == 0.0 {
+            return (0.0, 0.0);
+        }
+        (kernel * 100.0 / busy, user * 100.0 / busy)
+    }
+
+    ///
+    /// Is any individual core's usage above the threshold.
+    ///
+    /// Catches a single pegged core that a moderate aggregate usage hides. The aggregate itself
+    /// is not considered.
+    ///
+    pub fn any_core_saturated(&self, threshold: f64) -> bool {
+        self.per_core().any(|core| core.usage_percent() > threshold)
+    }
+
+    ///
+    /// Usage of the named core formatted as eg `cpu2: 37%`, `None` if the core is absent.
+    ///
+    pub fn format_core(&self, name: &str) -> Option<String> {
+        self.cores.iter()
+            .find(|core| core.stats.name == name)
+            .map(|core| format!("{}: {:.0}%", core.stats.name, core.usage_percent()))
+    }
+
+    ///
+    /// Is the spread between the least and most busy individual cores within the tolerance,
+    /// in percentage points.
+    ///
+    /// True if there are no individual cores.
+    ///
+    pub fn is_balanced(&self, tolerance: f64) -> bool {
+        if self.core_count() == 0 {
+            return true;
+        }
+        let usages = self.per_core().map(|core| core.usage_percent());
+        let (min, max) = usages.fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), usage| {
+            (min.min(usage), max.max(usage))
+        });
+        max - min <= tolerance
+    }
+
+    ///
+    /// Average usage of each group of SMT siblings as one figure per physical core.
+    ///
+    /// Each group is named after its first core. Cores missing from this snapshot are left
+    /// out of the average and groups with none present are skipped. Cores not in any group are
+    /// not reported.
+    ///
+    pub fn physical_core_usage(&self, siblings: &[Vec<String>]) -> Vec<(String, f64)> {
+        siblings.iter()
+            .filter_map(|group| {
+                let name = group.first()?;
+                let usages: Vec<f64> = group.iter()
+                    .filter_map(|name| self.per_core().find(|core| &core.stats.name == name))
+                    .map(|core| core.usage_percent())
+                    .collect();
+                if usages.is_empty() {
+                    return None;
+                }
+                Some((name.clone(), usages.iter().sum::<f64>() / usages.len() as f64))
+            })
+            .collect()
+    }
+
+    ///
+    /// Individual cores grouped by NUMA node using a map of core name to node.
+    ///
+    /// The map can come from `CPUStatsContext::numa_nodes`. Cores missing from it, and the
+    /// aggregate, are left out.
+    ///
+    pub fn by_numa_node(&self, node_map: &HashMap<String, u32>) -> BTreeMap<u32, Vec<&CoreSnapshot>> {
+        let mut nodes: BTreeMap<u32, Vec<&CoreSnapshot>> = BTreeMap::new();
+        for core in self.per_core() {
+            if let Some(&node) = node_map.get(&core.stats.name) {
+                nodes.entry(node).or_default().push(core);
+            }
+        }
+        nodes
+    }
+
+    ///
+    /// Classify the system by aggregate usage normalised by the core count, using the default
+    /// thresholds.
+    ///
+    pub fn classify(&self, core_count: usize) -> SystemState {
+        self.classify_with(core_count, StateThresholds::default())
+    }
+
+    ///
+    /// Classify the system by aggregate usage normalised by the core count.
+    ///
+    /// Idle if there is no aggregate or the core count is zero.
+    ///
+    pub fn classify_with(&self, core_count: usize, thresholds: StateThresholds) -> SystemState {
+        let usage = match (self.aggregate(), core_count) {
+            (Some(aggregate), count) if count > 0 => aggregate.usage_percent() / count as f64,
+            _ => return SystemState::Idle,
+        };
+        if usage >= thresholds.saturated {
+            SystemState::Saturated
+        } else if usage >= thresholds.busy {
+            SystemState::Busy
+        } else if usage >= thresholds.normal {
+            SystemState::Normal
+        } else {
+            SystemState::Idle
+        }
+    }
+
+    ///
+    /// Percentage of the whole system's time (0-100) spent waiting on I/O, dividing the
+    /// aggregate by the core count.
+    ///
+    /// A high value suggests the system is waiting on disk or network rather than computing.
+    /// I/O wait is not an accurate measure, a core waiting on I/O can run other work and the
+    /// counter can decrease, so treat it as a hint only. Zero if there is no aggregate or the
+    /// core count is zero.
+    ///
+    pub fn io_wait_percent_system(&self, core_count: usize) -> f64 {
+        match (self.aggregate(), core_count) {
+            (Some(aggregate), count) if count > 0 => aggregate.percent_of_period(aggregate.stats.io_wait) / count as f64,
+            _ => 0.0,
+        }
+    }
+
+    ///
+    /// Coefficient of variation of individual core usage, the population standard deviation
+    /// divided by the mean.
+    ///
+    /// Higher values indicate a more uneven load. Zero if there are no cores or the mean
+    /// usage is zero.
+    ///
+    pub fn usage_cv(&self) -> f64 {
+        let usages: Vec<f64> = self.per_core().map(|core| core.usage_percent()).collect();
+        if usages.is_empty() {
+            return 0.0;
+        }
+        let count = usages.len() as f64;
+        let mean = usages.iter().sum::<f64>() / count;
+        if mean == 0.0 {
+            return 0.0;
+        }
+        let variance = usages.iter().map(|usage| (usage - mean).powi(2)).sum::<f64>() / count;
+        variance.sqrt() / mean
+    }
+
+    ///
+    /// Cores whose usage moved by more than `min_usage_change` percentage points since the
+    /// previous snapshot, matched by name.
+    ///
+    /// Cores missing from the previous snapshot are included as they are new.
+    ///
+    pub fn significant_cores(&self, min_usage_change: f64, previous: &Snapshot) -> Vec<&CoreSnapshot> {
+        self.cores.iter()
+            .filter(|core| {
+                previous.cores.iter()
+                    .find(|earlier| earlier.stats.name == core.stats.name)
+                    .is_none_or(|earlier| (core.usage_percent() - earlier.usage_percent()).abs() > min_usage_change)
+            })
+            .collect()
+    }
+
+    ///
+    /// Render each core as a text usage bar `width` characters wide, one core per line.
+    ///
+    /// The aggregate is shown first, normalised to 0-100 for the whole system, eg:
+    ///
+    /// ```text
+    /// cpu  [|||||     ]  50%
+    /// cpu0 [||||||    ]  60%
+    /// cpu1 [||||      ]  40%
+    /// ```
+    ///
+    pub fn pretty(&self, width: usize) -> String {
+        let name_width = self.cores.iter().map(|core| core.stats.name.len()).max().unwrap_or(0);
+        let aggregate = self.aggregate().and_then(|aggregate| {
+            self.aggregate_usage().map(|usage| (aggregate, usage))
+        });
+        let cores = self.per_core().map(|core| (core, core.usage_percent()));
+
+        aggregate.into_iter().chain(cores)
+            .map(|(core, usage)| {
+                let usage = usage.clamp(0.0, 100.0);
+                let filled = ((usage / 100.0) * width as f64).round() as usize;
+                format!(
+                    "{:<name_width$} [{}{}] {:3.0}%",
+                    core.stats.name,
+                    "|".repeat(filled),
+                    " ".repeat(width - filled),
+                    usage,
+                    name_width = name_width,
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    ///
+    /// Render usage of every core, including the aggregate, in the Prometheus text format.
+    ///
+    pub fn to_prometheus(&self) -> String {
+        let mut text = String::from(
+            "# HELP cpu_usage_percent CPU usage over the last sample period\n\
+             # TYPE cpu_usage_percent gauge\n"
+        );
+        for core in &self.cores {
+            text.push_str(&format!("cpu_usage_percent{{cpu=\"{}\"}} {}\n", core.stats.name, core.usage_percent()));
+        }
+        text
+    }
+
+    ///
+    /// Is system wide usage (0-100) below the threshold.
+    ///
+    /// False if there is no aggregate or it accumulated no time.
+    ///
+    pub fn is_system_idle(&self, threshold: f64) -> bool {
+        self.aggregate_usage().is_some_and(|usage| usage < threshold)
+    }
+}
+
+impl From<Vec<CoreSnapshot>> for Snapshot {
+    fn from(cores: Vec<CoreSnapshot>) -> Self {
+        Snapshot::new(cores)
+    }
+}
+
+impl Deref for Snapshot {
+    type Target = [CoreSnapshot];
+
+    fn deref(&self) -> &[CoreSnapshot] {
+        &self.cores
+    }
+}
+
+impl IntoIterator for Snapshot {
+    type Item = CoreSnapshot;
+    type IntoIter = std::vec::IntoIter<CoreSnapshot>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.cores.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a Snapshot {
+    type Item = &'a CoreSnapshot;
+    type IntoIter = std::slice::Iter<'a, CoreSnapshot>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.cores.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{core_snapshot, USER_HZ};
+
+    #[test]
+    fn sorted_by_index() {
+        let snapshot = Snapshot::new(vec![
+            core_snapshot("cpu10 0 0 0 0 0 0 0 0 0 0", 1000),
+            core_snapshot("cpu2 0 0 0 0 0 0 0 0 0 0", 1000),
+            core_snapshot("cpu  0 0 0 0 0 0 0 0 0 0", 1000),
+            core_snapshot("cpu0 0 0 0 0 0 0 0 0 0 0", 1000),
+            core_snapshot("cpu1 0 0 0 0 0 0 0 0 0 0", 1000),
+        ]);
+        let names: Vec<String> = snapshot.sorted_by_index().into_iter()
+            .map(|core| core.stats.name)
+            .collect();
+
+        assert_eq!(names, vec!["cpu", "cpu0", "cpu1", "cpu2", "cpu10"]);
+    }
+
+    #[test]
+    fn least_busy_core() {
+        let snapshot = Snapshot::new(vec![
+            core_snapshot("cpu  90 0 0 210 0 0 0 0 0 0", 1000),
+            core_snapshot("cpu2 30 0 0 70 0 0 0 0 0 0", 1000),
+            core_snapshot("cpu0 50 0 0 50 0 0 0 0 0 0", 1000),
+            core_snapshot("cpu1 10 0 0 90 0 0 0 0 0 0", 1000),
+            core_snapshot("cpu3 10 0 0 90 0 0 0 0 0 0", 1000),
+        ]);
+        assert_eq!(snapshot.least_busy_core().unwrap().stats.name, "cpu1");
+
+        assert!(Snapshot::new(vec![core_snapshot("cpu  90 0 0 210 0 0 0 0 0 0", 1000)]).least_busy_core().is_none());
+    }
+
+    #[test]
+    fn total_active_jiffies() {
+        let snapshot = Snapshot::new(vec![
+            core_snapshot("cpu  45 2 14 130 6 4 2 0 0 0", 1000),
+            core_snapshot("cpu0 20 1 5 70 4 1 1 0 0 0", 1000),
+            core_snapshot("cpu1 25 1 9 60 2 3 1 0 0 0", 1000),
+        ]);
+
+        assert_eq!(snapshot.total_active_jiffies(), 28 + 39);
+
+        let saturated = Snapshot::new(vec![
+            core_snapshot(&format!("cpu0 {} 0 0 0 0 0 0 0 0 0", u64::MAX), 1000),
+            core_snapshot("cpu1 1 0 0 0 0 0 0 0 0 0", 1000),
+        ]);
+        assert_eq!(saturated.total_active_jiffies(), u64::MAX);
+    }
+
+    #[test]
+    fn pretty() {
+        let snapshot = Snapshot::new(vec![
+            core_snapshot("cpu  80 0 20 100 0 0 0 0 0 0", 1000),
+            core_snapshot("cpu0 50 0 10 40 0 0 0 0 0 0", 1000),
+            core_snapshot("cpu1 30 0 10 60 0 0 0 0 0 0", 1000),
+        ]);
+
+        assert_eq!(
+            snapshot.pretty(10),
+            "cpu  [|||||     ]  50%\ncpu0 [||||||    ]  60%\ncpu1 [||||      ]  40%"
+        );
+        assert!(snapshot.pretty(20).lines().nth(1).unwrap().contains(&format!("[{}{}]", "|".repeat(12), " ".repeat(8))));
+    }
+
+    #[test]
+    fn to_prometheus() {
+        let snapshot = Snapshot::new(vec![
+            core_snapshot("cpu  80 0 20 100 0 0 0 0 0 0", 1000),
+            core_snapshot("cpu0 50 0 10 40 0 0 0 0 0 0", 1000),
+        ]);
+
+        assert_eq!(snapshot.to_prometheus(), "\
+# HELP cpu_usage_percent CPU usage over the last sample period
+# TYPE cpu_usage_percent gauge
+cpu_usage_percent{cpu=\"cpu\"} 100
+cpu_usage_percent{cpu=\"cpu0\"} 60
+");
+    }
+
+    #[test]
+    fn aggregate_usage() {
+        let snapshot = Snapshot::new(vec![
+            core_snapshot("cpu  170 10 20 0 0 0 0 0 0 0", 1000),
+            core_snapshot("cpu0 90 0 10 0 0 0 0 0 0 0", 1000),
+            core_snapshot("cpu1 80 10 10 0 0 0 0 0 0 0", 1000),
+        ]);
+        assert_eq!(snapshot.aggregate_usage(), Some(100.0));
+
+        let snapshot = Snapshot::new(vec![
+            core_snapshot("cpu  45 5 10 140 0 0 0 0 0 0", 1000),
+            core_snapshot("cpu0 20 5 5 70 0 0 0 0 0 0", 1000),
+            core_snapshot("cpu1 25 0 5 70 0 0 0 0 0 0", 1000),
+        ]);
+        assert_eq!(snapshot.aggregate_usage(), Some(30.0));
+
+        assert_eq!(Snapshot::new(vec![core_snapshot("cpu0 20 5 5 70 0 0 0 0 0 0", 1000)]).aggregate_usage(), None);
+        assert_eq!(Snapshot::new(vec![core_snapshot("cpu  0 0 0 0 0 0 0 0 0 0", 1000)]).aggregate_usage(), None);
+    }
+
+    #[test]
+    fn aggregate_usage_restricted_cores() {
+        // 8 busy cores with only 2 in the snapshot, eg restricted to an affinity set
+        let snapshot = Snapshot::new(vec![
+            core_snapshot("cpu  700 0 60 40 0 0 0 0 0 0", 1000),
+            core_snapshot("cpu2 90 0 5 5 0 0 0 0 0 0", 1000),
+            core_snapshot("cpu3 85 0 10 5 0 0 0 0 0 0", 1000),
+        ]);
+
+        assert_eq!(snapshot.aggregate_usage(), Some(95.0));
+        assert!(!snapshot.is_system_idle(50.0));
+        assert!(snapshot.pretty(10).starts_with("cpu  [||||||||||]  95%"));
+    }
+
+    #[test]
+    fn is_system_idle() {
+        let quiet = Snapshot::new(vec![
+            core_snapshot("cpu  12 0 4 184 0 0 0 0 0 0", 1000),
+            core_snapshot("cpu0 8 0 2 90 0 0 0 0 0 0", 1000),
+            core_snapshot("cpu1 4 0 2 94 0 0 0 0 0 0", 1000),
+        ]);
+        assert!(quiet.is_system_idle(10.0));
+
+        let busy = Snapshot::new(vec![
+            core_snapshot("cpu  120 0 40 40 0 0 0 0 0 0", 1000),
+            core_snapshot("cpu0 70 0 20 10 0 0 0 0 0 0", 1000),
+            core_snapshot("cpu1 50 0 20 30 0 0 0 0 0 0", 1000),
+        ]);
+        assert!(!busy.is_system_idle(10.0));
+    }
+
+    #[test]
+    fn aggregate_vs_sum_discrepancy() {
+        let complete = Snapshot::new(vec![
+            core_snapshot("cpu  45 2 14 130 6 4 2 0 0 0", 1000),
+            core_snapshot("cpu0 20 1 5 70 4 1 1 0 0 0", 1000),
+            core_snapshot("cpu1 25 1 9 60 2 3 1 0 0 0", 1000),
+        ]);
+        assert_eq!(complete.aggregate_vs_sum_discrepancy(), Some(0));
+
+        let missing = Snapshot::new(vec![
+            core_snapshot("cpu  45 2 14 130 6 4 2 0 0 0", 1000),
+            core_snapshot("cpu0 20 1 5 70 4 1 1 0 0 0", 1000),
+        ]);
+        assert_eq!(missing.aggregate_vs_sum_discrepancy(), Some(39));
+
+        let no_aggregate = Snapshot::new(vec![core_snapshot("cpu0 20 1 5 70 4 1 1 0 0 0", 1000)]);
+        assert_eq!(no_aggregate.aggregate_vs_sum_discrepancy(), None);
+
+        let saturated = Snapshot::new(vec![
+            core_snapshot("cpu  0 0 0 0 0 0 0 0 0 0", 1000),
+            core_snapshot(&format!("cpu0 {} 0 0 0 0 0 0 0 0 0", u64::MAX), 1000),
+        ]);
+        assert_eq!(saturated.aggregate_vs_sum_discrepancy(), Some(i64::MIN));
+
+        let half = Snapshot::new(vec![
+            core_snapshot(&format!("cpu  {} 0 0 0 0 0 0 0 0 0", u64::MAX), 1000),
+            core_snapshot(&format!("cpu0 {} 0 0 0 0 0 0 0 0 0", 1u64 << 63), 1000),
+        ]);
+        assert_eq!(half.aggregate_vs_sum_discrepancy(), Some(i64::MAX));
+    }
+
+    #[test]
+    fn kernel_user_split() {
+        let snapshot = Snapshot::new(vec![
+            core_snapshot("cpu  50 10 30 100 5 6 4 7 0 0", 1000),
+            core_snapshot("cpu0 50 10 30 100 5 6 4 7 0 0", 1000),
+        ]);
+        assert_eq!(snapshot.kernel_user_split(), (40.0, 60.0));
+
+        let idle = Snapshot::new(vec![core_snapshot("cpu  0 0 0 100 0 0 0 0 0 0", 1000)]);
+        assert_eq!(idle.kernel_user_split(), (0.0, 0.0));
+        assert_eq!(Snapshot::new(Vec::new()).kernel_user_split(), (0.0, 0.0));
+    }
+
+    #[test]
+    fn from_vec() {
+        let snapshot = Snapshot::from(vec![
+            core_snapshot("cpu  80 0 20 100 0 0 0 0 0 0", 1000),
+            core_snapshot("cpu0 50 0 10 40 0 0 0 0 0 0", 1000),
+            core_snapshot("cpu1 30 0 10 60 0 0 0 0 0 0", 1000),
+        ]);
+
+        assert_eq!(snapshot.len(), 3);
+        assert_eq!(snapshot[1].stats.name, "cpu0");
+        assert_eq!(snapshot.iter().filter(|core| core.usage_percent() > 50.0).count(), 2);
+        assert_eq!(snapshot.core_count(), 2);
+        assert_eq!(snapshot.aggregate_usage(), Some(50.0));
+
+        let names: Vec<String> = snapshot.into_iter().map(|core| core.stats.name).collect();
+        assert_eq!(names, vec!["cpu", "cpu0", "cpu1"]);
+    }
+
+    #[test]
+    fn any_core_saturated() {
+        let snapshot = Snapshot::new(vec![
+            core_snapshot("cpu  115 0 5 280 0 0 0 0 0 0", 1000),
+            core_snapshot("cpu0 98 0 2 0 0 0 0 0 0 0", 1000),
+            core_snapshot("cpu1 5 0 1 94 0 0 0 0 0 0", 1000),
+            core_snapshot("cpu2 7 0 1 92 0 0 0 0 0 0", 1000),
+            core_snapshot("cpu3 5 0 1 94 0 0 0 0 0 0", 1000),
+        ]);
+        assert_eq!(snapshot.aggregate_usage(), Some(30.0));
+        assert!(snapshot.any_core_saturated(95.0));
+
+        let even = Snapshot::new(vec![
+            core_snapshot("cpu  120 0 0 80 0 0 0 0 0 0", 1000),
+            core_snapshot("cpu0 60 0 0 40 0 0 0 0 0 0", 1000),
+            core_snapshot("cpu1 60 0 0 40 0 0 0 0 0 0", 1000),
+        ]);
+        assert!(!even.any_core_saturated(95.0));
+    }
+
+    #[test]
+    fn total_busy_duration() {
+        let snapshot = Snapshot::new(vec![
+            core_snapshot("cpu  90 0 30 80 0 0 0 0 0 0", 1000),
+            core_snapshot("cpu0 60 0 20 20 0 0 0 0 0 0", 1000),
+            core_snapshot("cpu1 30 0 10 60 0 0 0 0 0 0", 1000),
+        ]);
+
+        assert_eq!(snapshot.total_busy_duration(USER_HZ), Duration::from_millis(1200));
+        assert_eq!(snapshot.total_busy_duration(250), Duration::from_millis(480));
+    }
+
+    #[test]
+    fn total_idle_duration() {
+        let snapshot = Snapshot::new(vec![
+            core_snapshot("cpu  90 0 30 80 5 0 0 0 0 0", 1000),
+            core_snapshot("cpu0 60 0 20 20 5 0 0 0 0 0", 1000),
+            core_snapshot("cpu1 30 0 10 60 0 0 0 0 0 0", 1000),
+        ]);
+
+        assert_eq!(snapshot.total_idle_duration(USER_HZ), Duration::from_millis(800));
+        assert_eq!(snapshot.total_idle_duration(250), Duration::from_millis(320));
+
+        let saturated = Snapshot::new(vec![
+            core_snapshot(&format!("cpu0 0 0 0 {} 0 0 0 0 0 0", u64::MAX), 1000),
+            core_snapshot("cpu1 0 0 0 1 0 0 0 0 0 0", 1000),
+        ]);
+        assert_eq!(saturated.total_idle_duration(1), Duration::from_secs(u64::MAX));
+    }
+
+    #[test]
+    fn is_balanced() {
+        let balanced = Snapshot::new(vec![
+            core_snapshot("cpu  148 0 0 52 0 0 0 0 0 0", 1000),
+            core_snapshot("cpu0 72 0 0 28 0 0 0 0 0 0", 1000),
+            core_snapshot("cpu1 76 0 0 24 0 0 0 0 0 0", 1000),
+        ]);
+        assert!(balanced.is_balanced(5.0));
+
+        let skewed = Snapshot::new(vec![
+            core_snapshot("cpu  176 0 0 24 0 0 0 0 0 0", 1000),
+            core_snapshot("cpu0 96 0 0 4 0 0 0 0 0 0", 1000),
+            core_snapshot("cpu1 80 0 0 20 0 0 0 0 0 0", 1000),
+        ]);
+        assert!(!skewed.is_balanced(5.0));
+        assert!(Snapshot::new(Vec::new()).is_balanced(0.0));
+    }
+
+    #[test]
+    fn physical_core_usage() {
+        let snapshot = Snapshot::new(vec![
+            core_snapshot("cpu  150 0 0 250 0 0 0 0 0 0", 1000),
+            core_snapshot("cpu0 80 0 0 20 0 0 0 0 0 0", 1000),
+            core_snapshot("cpu1 10 0 0 90 0 0 0 0 0 0", 1000),
+            core_snapshot("cpu2 40 0 0 60 0 0 0 0 0 0", 1000),
+            core_snapshot("cpu3 20 0 0 80 0 0 0 0 0 0", 1000),
+        ]);
+        let siblings = vec![
+            vec!["cpu0".to_string(), "cpu2".to_string()],
+            vec!["cpu1".to_string(), "cpu3".to_string()],
+            vec!["cpu4".to_string(), "cpu5".to_string()],
+        ];
+
+        assert_eq!(snapshot.physical_core_usage(&siblings), vec![
+            ("cpu0".to_string(), 60.0),
+            ("cpu1".to_string(), 15.0),
+        ]);
+    }
+
+    #[test]
+    fn classify() {
+        let state = |user: u64| {
+            Snapshot::new(vec![core_snapshot(&format!("cpu  {} 0 0 {} 0 0 0 0 0 0", user, 400 - user), 1000)]).classify(4)
+        };
+
+        assert_eq!(state(20), SystemState::Idle);
+        assert_eq!(state(40), SystemState::Normal);
+        assert_eq!(state(200), SystemState::Normal);
+        assert_eq!(state(280), SystemState::Busy);
+        assert_eq!(state(360), SystemState::Saturated);
+        assert_eq!(state(400), SystemState::Saturated);
+        assert_eq!(Snapshot::new(Vec::new()).classify(4), SystemState::Idle);
+
+        let strict = StateThresholds { normal: 5.0, busy: 40.0, saturated: 60.0 };
+        let snapshot = Snapshot::new(vec![core_snapshot("cpu  200 0 0 200 0 0 0 0 0 0", 1000)]);
+        assert_eq!(snapshot.classify_with(4, strict), SystemState::Busy);
+    }
+
+    #[test]
+    fn usage_cv() {
+        let uneven = Snapshot::new(vec![
+            core_snapshot("cpu  160 0 0 240 0 0 0 0 0 0", 1000),
+            core_snapshot("cpu0 20 0 0 80 0 0 0 0 0 0", 1000),
+            core_snapshot("cpu1 40 0 0 60 0 0 0 0 0 0", 1000),
+            core_snapshot("cpu2 40 0 0 60 0 0 0 0 0 0", 1000),
+            core_snapshot("cpu3 60 0 0 40 0 0 0 0 0 0", 1000),
+        ]);
+        assert!((uneven.usage_cv() - 0.353_553).abs() < 1e-6);
+
+        let even = Snapshot::new(vec![
+            core_snapshot("cpu0 50 0 0 50 0 0 0 0 0 0", 1000),
+            core_snapshot("cpu1 50 0 0 50 0 0 0 0 0 0", 1000),
+        ]);
+        assert_eq!(even.usage_cv(), 0.0);
+
+        let idle = Snapshot::new(vec![core_snapshot("cpu0 0 0 0 100 0 0 0 0 0 0", 1000)]);
+        assert_eq!(idle.usage_cv(), 0.0);
+        assert_eq!(Snapshot::new(Vec::new()).usage_cv(), 0.0);
+    }
+
+    #[test]
+    fn significant_cores() {
+        let previous = Snapshot::new(vec![
+            core_snapshot("cpu  90 0 0 110 0 0 0 0 0 0", 1000),
+            core_snapshot("cpu0 50 0 0 50 0 0 0 0 0 0", 1000),
+            core_snapshot("cpu1 40 0 0 60 0 0 0 0 0 0", 1000),
+        ]);
+        let current = Snapshot::new(vec![
+            core_snapshot("cpu  95 0 0 105 0 0 0 0 0 0", 1000),
+            core_snapshot("cpu0 52 0 0 48 0 0 0 0 0 0", 1000),
+            core_snapshot("cpu1 43 0 0 57 0 0 0 0 0 0", 1000),
+            core_snapshot("cpu2 1 0 0 99 0 0 0 0 0 0", 1000),
+        ]);
+        let names = |cores: Vec<&CoreSnapshot>| cores.iter().map(|core| core.stats.name.clone()).collect::<Vec<String>>();
+
+        assert_eq!(names(current.significant_cores(4.0, &previous)), vec!["cpu", "cpu2"]);
+
+        let spike = Snapshot::new(vec![
+            core_snapshot("cpu0 51 0 0 49 0 0 0 0 0 0", 1000),
+            core_snapshot("cpu1 90 0 0 10 0 0 0 0 0 0", 1000),
+        ]);
+        assert_eq!(names(spike.significant_cores(10.0, &previous)), vec!["cpu1"]);
+    }
+
+    #[test]
+    fn format_core() {
+        let snapshot = Snapshot::new(vec![
+            core_snapshot("cpu  70 0 4 126 0 0 0 0 0 0", 1000),
+            core_snapshot("cpu0 30 0 3 67 0 0 0 0 0 0", 1000),
+            core_snapshot("cpu2 37 0 0 63 0 0 0 0 0 0", 1000),
+        ]);
+
+        assert_eq!(snapshot.format_core("cpu2"), Some("cpu2: 37%".to_string()));
+        assert_eq!(snapshot.format_core("cpu"), Some("cpu: 74%".to_string()));
+        assert_eq!(snapshot.format_core("cpu1"), None);
+    }
+
+    #[test]
+    fn io_wait_percent_system() {
+        let snapshot = Snapshot::new(vec![
+            core_snapshot("cpu  60 0 20 200 120 0 0 0 0 0", 1000),
+            core_snapshot("cpu0 20 0 10 30 40 0 0 0 0 0", 1000),
+            core_snapshot("cpu1 10 0 0 10 80 0 0 0 0 0", 1000),
+            core_snapshot("cpu2 20 0 5 75 0 0 0 0 0 0", 1000),
+            core_snapshot("cpu3 10 0 5 85 0 0 0 0 0 0", 1000),
+        ]);
+
+        assert_eq!(snapshot.io_wait_percent_system(4), 30.0);
+        assert_eq!(snapshot.io_wait_percent_system(0), 0.0);
+        assert_eq!(Snapshot::new(vec![core_snapshot("cpu0 0 0 0 50 50 0 0 0 0 0", 1000)]).io_wait_percent_system(1), 0.0);
+    }
+}
@@ -0,0 +1,97 @@
+///
+/// Sources of stats file contents
+///
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::PathBuf;
+
+use crate::STATS_FILE;
+
+///
+/// Provides the contents of a stats file in `/proc/stat` format.
+///
+/// Implement this to read stats from somewhere other than the local proc file system.
+///
+pub trait StatsSource: Send {
+    ///
+    /// Read the complete current contents of the stats file
+    ///
+    fn read_stats(&mut self) -> io::Result<String>;
+}
+
+///
+/// Stats read from a file, by default `/proc/stat`
+///
+pub struct StatsFile {
+    path: PathBuf,
+}
+
+impl StatsFile {
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+impl Default for StatsFile {
+    fn default() -> Self {
+        StatsFile::new(STATS_FILE)
+    }
+}
+
+impl StatsSource for StatsFile {
+    fn read_stats(&mut self) -> io::Result<String> {
+        fs::read_to_string(&self.path)
+    }
+}
+
+///
+/// Stats read from an already open file, rewound before each read.
+///
+/// For sandboxed processes handed a pre-opened `/proc/stat` rather than being able to open it.
+///
+pub struct OpenStatsFile {
+    file: File,
+}
+
+impl OpenStatsFile {
+    pub fn new(file: File) -> Self {
+        Self { file }
+    }
+}
+
+impl StatsSource for OpenStatsFile {
+    fn read_stats(&mut self) -> io::Result<String> {
+        self.file.seek(SeekFrom::Start(0))?;
+        let mut contents = String::new();
+        self.file.read_to_string(&mut contents)?;
+        Ok(contents)
+    }
+}
+
+///
+/// Source returning each of a fixed set of contents in turn, repeating the last
+///
+#[cfg(test)]
+pub(crate) struct Sequence {
+    contents: Vec<String>,
+    next: usize,
+}
+
+#[cfg(test)]
+impl Sequence {
+    pub(crate) fn new(contents: &[&str]) -> Self {
+        Self {
+            contents: contents.iter().map(|c| c.to_string()).collect(),
+            next: 0,
+        }
+    }
+}
+
+#[cfg(test)]
+impl StatsSource for Sequence {
+    fn read_stats(&mut self) -> io::Result<String> {
+        let contents = self.contents[self.next.min(self.contents.len() - 1)].clone();
+        self.next += 1;
+        Ok(contents)
+    }
+}
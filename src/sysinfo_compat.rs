@@ -0,0 +1,53 @@
+///
+/// Interop with code written against the `sysinfo` crate
+///
+use crate::CoreSnapshot;
+
+///
+/// Mirrors the usage related parts of `sysinfo::Cpu`
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cpu {
+    name: String,
+    cpu_usage: f32,
+}
+
+impl Cpu {
+    ///
+    /// Name of the core
+    ///
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    ///
+    /// Usage percentage over the last period
+    ///
+    pub fn cpu_usage(&self) -> f32 {
+        self.cpu_usage
+    }
+}
+
+impl From<&CoreSnapshot> for Cpu {
+    fn from(snapshot: &CoreSnapshot) -> Self {
+        Self {
+            name: snapshot.stats.name.clone(),
+            cpu_usage: snapshot.usage_percent() as f32,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core_snapshot;
+
+    #[test]
+    fn from_snapshot() {
+        let snapshot = core_snapshot("cpu3 17 1 5 70 0 0 0 0 0 0", 1000);
+        let cpu = Cpu::from(&snapshot);
+
+        assert_eq!(cpu.name(), "cpu3");
+        assert!((cpu.cpu_usage() as f64 - snapshot.usage_percent()).abs() < 1e-4);
+    }
+}
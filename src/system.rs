@@ -0,0 +1,170 @@
+///
+/// System wide counters from the non-cpu lines of the stats file, all counts are
+/// aggregates since system boot.
+///
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SystemStats {
+    ///
+    /// Total interrupts serviced
+    ///
+    pub interrupts: u64,
+
+    ///
+    /// Interrupts serviced for each numbered IRQ, only populated when per-IRQ tracking is
+    /// enabled on the context as there can be thousands of them
+    ///
+    pub per_irq: Vec<u64>,
+
+    ///
+    /// Processes and threads created (forks)
+    ///
+    pub processes: u64,
+
+    ///
+    /// Context switches across all cores
+    ///
+    pub context_switches: u64,
+
+    ///
+    /// Processes currently runnable, an instantaneous count rather than a counter
+    ///
+    running: u64,
+
+    ///
+    /// Processes currently blocked waiting for I/O, an instantaneous count rather than a counter
+    ///
+    blocked: u64,
+
+    ///
+    /// Individual cores listed in the file, before any restriction by the context
+    ///
+    online_cores: usize,
+}
+
+impl SystemStats {
+    ///
+    /// Parse the system lines of a stats file, optionally including the per-IRQ counts.
+    ///
+    /// Lines that are missing or malformed are left as zero.
+    ///
+    pub(crate) fn parse(contents: &str, per_irq: bool) -> Self {
+        let mut stats = SystemStats::default();
+        for line in contents.lines() {
+            // Count the core lines without splitting them, on large machines they are most of the file
+            if line.starts_with("cpu") {
+                if line.as_bytes().get(3).is_some_and(u8::is_ascii_digit) {
+                    stats.online_cores += 1;
+                }
+                continue;
+            }
+            let mut atoms = line.split_whitespace();
+            let label = atoms.next();
+            let mut values = atoms.map(|word| word.parse::<u64>().unwrap_or(0));
+            match label {
+                Some("intr") => {
+                    stats.interrupts = values.next().unwrap_or(0);
+                    if per_irq {
+                        stats.per_irq = values.collect();
+                    }
+                }
+                Some("ctxt") => stats.context_switches = values.next().unwrap_or(0),
+                Some("processes") => stats.processes = values.next().unwrap_or(0),
+                Some("procs_running") => stats.running = values.next().unwrap_or(0),
+                Some("procs_blocked") => stats.blocked = values.next().unwrap_or(0),
+                _ => {}
+            }
+        }
+        stats
+    }
+
+    ///
+    /// Processes runnable at the time of the read, from `procs_running`
+    ///
+    pub fn runnable(&self) -> u64 {
+        self.running
+    }
+
+    ///
+    /// Processes blocked on I/O at the time of the read, from `procs_blocked`
+    ///
+    pub fn blocked(&self) -> u64 {
+        self.blocked
+    }
+
+    ///
+    /// Number of individual cores in the stats file, including any the context does not track
+    ///
+    pub fn online_cores(&self) -> usize {
+        self.online_cores
+    }
+
+    ///
+    /// Processes created per second between an earlier reading and this one
+    ///
+    pub(crate) fn fork_rate(&self, earlier: &Self, period_secs: f64) -> f64 {
+        counter_rate(self.processes, earlier.processes, period_secs)
+    }
+
+    ///
+    /// Context switches per second between an earlier reading and this one
+    ///
+    pub(crate) fn context_switch_rate(&self, earlier: &Self, period_secs: f64) -> f64 {
+        counter_rate(self.context_switches, earlier.context_switches, period_secs)
+    }
+
+    ///
+    /// Rate per second of each IRQ between an earlier reading and this one
+    ///
+    pub(crate) fn per_irq_rates(&self, earlier: &Self, period_secs: f64) -> Vec<f64> {
+        if period_secs <= 0.0 {
+            return vec![0.0; self.per_irq.len()];
+        }
+        self.per_irq.iter().zip(&earlier.per_irq)
+            .map(|(now, then)| now.saturating_sub(*then) as f64 / period_secs)
+            .collect()
+    }
+}
+
+///
+/// Per second rate of a counter that should only increase, zero if the period is zero
+///
+fn counter_rate(now: u64, then: u64, period_secs: f64) -> f64 {
+    if period_secs <= 0.0 {
+        return 0.0;
+    }
+    now.saturating_sub(then) as f64 / period_secs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROC_STAT: &str = "\
+cpu  400 20 300 8000 40 10 5 0 0 0
+cpu0 400 20 300 8000 40 10 5 0 0 0
+intr 1187 9 0 42 0 1136
+ctxt 67890
+processes 4321
+";
+
+    #[test]
+    fn parse_per_irq() {
+        let stats = SystemStats::parse(PROC_STAT, true);
+        assert_eq!(stats.interrupts, 1187);
+        assert_eq!(stats.per_irq, vec![9, 0, 42, 0, 1136]);
+
+        let stats = SystemStats::parse(PROC_STAT, false);
+        assert_eq!(stats.interrupts, 1187);
+        assert!(stats.per_irq.is_empty());
+    }
+
+    #[test]
+    fn fork_rate() {
+        let earlier = SystemStats::parse(PROC_STAT, false);
+        let now = SystemStats { processes: 4521, ..earlier.clone() };
+
+        assert_eq!(earlier.processes, 4321);
+        assert_eq!(now.fork_rate(&earlier, 2.0), 100.0);
+        assert_eq!(now.fork_rate(&earlier, 0.0), 0.0);
+    }
+}
@@ -0,0 +1,81 @@
+extern crate cpu_stats;
+
+use std::fs;
+use cpu_stats::{parse_stats, read_raw_from, CPUStatsContext};
+
+const FIXTURE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/proc_stat_8core.txt");
+
+#[test]
+fn parse_8core_fixture() {
+    let contents = fs::read_to_string(FIXTURE).unwrap();
+    let cores = parse_stats(&contents).unwrap();
+
+    assert_eq!(cores.len(), 9);
+    assert!(cores[0].is_aggregate());
+    for (index, core) in cores[1..].iter().enumerate() {
+        assert!(!core.is_aggregate());
+        assert_eq!(core.name, format!("cpu{}", index));
+    }
+
+    let aggregate = &cores[0];
+    assert_eq!(aggregate.user_processes, 1854211);
+    assert_eq!(aggregate.nice_processes, 3190);
+    assert_eq!(aggregate.system_processes, 612377);
+    assert_eq!(aggregate.idle_time, 59822025);
+    assert_eq!(aggregate.io_wait, 48204);
+    assert_eq!(aggregate.irq, 0);
+    assert_eq!(aggregate.soft_irq, 21693);
+    assert_eq!(aggregate.steal_time, 0);
+
+    let cpu3 = &cores[4];
+    assert_eq!(cpu3.user_processes, 230128);
+    assert_eq!(cpu3.idle_time, 7483880);
+    assert_eq!(cpu3.soft_irq, 1744);
+
+    let summed_user: u64 = cores[1..].iter().map(|core| core.user_processes).sum();
+    assert_eq!(summed_user, aggregate.user_processes);
+}
+
+#[test]
+fn context_from_path() {
+    let mut stats_context = CPUStatsContext::from_path(FIXTURE).unwrap();
+    assert_eq!(stats_context.last_stats.len(), 9);
+
+    let snapshots = stats_context.read().unwrap();
+    assert_eq!(snapshots.len(), 9);
+    assert!(snapshots.iter().all(|core| core.stats.active_time() == 0));
+}
+
+#[test]
+fn runnable_and_blocked() {
+    let stats_context = CPUStatsContext::from_path(FIXTURE).unwrap();
+    let system = stats_context.system_stats();
+
+    assert_eq!(system.runnable(), 3);
+    assert_eq!(system.blocked(), 1);
+}
+
+#[test]
+fn read_raw_from_fixture() {
+    let cores = read_raw_from(FIXTURE).unwrap();
+
+    assert_eq!(cores.len(), 9);
+    assert!(cores[0].is_aggregate());
+    assert_eq!(cores[0].user_processes, 1854211);
+    assert_eq!(cores[8].name, "cpu7");
+}
+
+#[cfg(unix)]
+#[test]
+fn context_from_raw_fd() {
+    use std::os::unix::io::IntoRawFd;
+
+    let fd = fs::File::open(FIXTURE).unwrap().into_raw_fd();
+    let mut stats_context = unsafe { CPUStatsContext::from_raw_fd(fd) }.unwrap();
+    assert_eq!(stats_context.last_stats.len(), 9);
+
+    for _ in 0..2 {
+        let snapshots = stats_context.read().unwrap();
+        assert_eq!(snapshots.len(), 9);
+    }
+}
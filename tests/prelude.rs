@@ -0,0 +1,15 @@
+extern crate cpu_stats;
+
+use cpu_stats::prelude::*;
+
+const FIXTURE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/proc_stat_8core.txt");
+
+#[test]
+fn context_from_prelude() {
+    let mut stats_context = CPUStatsContext::from_path(FIXTURE).unwrap();
+    let snapshot: Snapshot = stats_context.read_snapshot().unwrap();
+
+    assert_eq!(snapshot.core_count(), 8);
+    let aggregate: &CoreSnapshot = snapshot.aggregate().unwrap();
+    assert_eq!(aggregate.usage_percent_rounded(RoundingMode::Floor), 0);
+}